@@ -0,0 +1,128 @@
+use wgpu::{Color, TextureFormat};
+
+/// The clear color used when `--clear-color` isn't given.
+pub const DEFAULT_CLEAR_COLOR: Color = Color {
+    r: 0.6509803921568628,
+    g: 0.8901960784313725,
+    b: 0.6313725490196078,
+    a: 1.0,
+};
+
+/// An 8-bit per channel sRGB color parsed from `RRGGBB` or `RRGGBBAA`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexColor([u8; 4]);
+
+impl HexColor {
+    pub fn parse(s: &str) -> Result<HexColor, String> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+            return Err(format!("expected RRGGBB or RRGGBBAA, got {:?}", s));
+        }
+
+        // `from_str_radix` would also accept a sign.
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid hex digits in {:?}", s));
+        }
+
+        let mut channels = [u8::MAX; 4];
+        for (channel, i) in channels.iter_mut().zip((0..hex.len()).step_by(2)) {
+            *channel = u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        }
+        Ok(Self(channels))
+    }
+
+    /// Converts to a clear color for a target of `format`. sRGB targets encode
+    /// on write, so the channels are converted to linear first to keep the
+    /// color on screen matching the hex value.
+    pub fn to_color(self, format: TextureFormat) -> Color {
+        let [r, g, b, a] = self.0.map(|c| c as f64 / 255.0);
+        if format.is_srgb() {
+            Color {
+                r: srgb_to_linear(r),
+                g: srgb_to_linear(g),
+                b: srgb_to_linear(b),
+                a,
+            }
+        } else {
+            Color { r, g, b, a }
+        }
+    }
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear color as sRGB, for writing to a target that doesn't encode itself.
+pub fn linear_to_srgb(color: Color) -> Color {
+    let encode = |c: f64| {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    Color {
+        r: encode(color.r),
+        g: encode(color.g),
+        b: encode(color.b),
+        a: color.a,
+    }
+}
+
+/// A random but reproducible clear color for `frame`, the same for every run
+/// with the same `seed`.
+pub fn frame_color(seed: u64, frame: u64) -> Color {
+    // SplitMix64, seeded by both so any frame's color can be computed directly.
+    let mut state = seed ^ frame.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        (z ^ (z >> 31)) as f64 / u64::MAX as f64
+    };
+    Color {
+        r: next(),
+        g: next(),
+        b: next(),
+        a: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(HexColor::parse("ff8000"), Ok(HexColor([255, 128, 0, 255])));
+        assert_eq!(HexColor::parse("#ff8000"), Ok(HexColor([255, 128, 0, 255])));
+        assert_eq!(
+            HexColor::parse("FF800080"),
+            Ok(HexColor([255, 128, 0, 128]))
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_hex_colors() {
+        for s in [
+            "",
+            "#",
+            "fff",
+            "ff800",
+            "ff80000",
+            "ff8000801",
+            "gg0000",
+            "+f8000",
+            "ff 800",
+            "éé00",
+        ] {
+            assert!(HexColor::parse(s).is_err(), "{:?} parsed", s);
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use log::{error, info};
+use wgpu::Backends;
+
+use crate::config::{BackendArg, Config};
+use crate::gpu;
+use crate::headless;
+use crate::stats::{self, LatencyStats};
+use crate::FrameStats;
+
+/// Every backend that `--compare-backends` tries, in order.
+const BACKENDS: [BackendArg; 4] = [
+    BackendArg::Vulkan,
+    BackendArg::Dx12,
+    BackendArg::Metal,
+    BackendArg::Gl,
+];
+
+/// Runs `frames` headless frames on each backend that has an adapter, one
+/// after the other with a fresh device each, then logs a table comparing them.
+/// A backend whose device or resources couldn't be created is listed as
+/// failed. `on_frame` is called with every frame on every backend.
+pub fn compare_backends(options: &Config, frames: u64, mut on_frame: impl FnMut(&FrameStats)) {
+    let mut results = Vec::new();
+    for backend in BACKENDS {
+        let options = Config {
+            backend,
+            ..options.clone()
+        };
+        let adapters = gpu::create_instance(&options).enumerate_adapters(Backends::from(backend));
+        if adapters.is_empty() {
+            info!("Skipping {:?}, it has no adapters", backend);
+            continue;
+        }
+
+        info!("Comparing backends: running on {:?}", backend);
+        let stats = pollster::block_on(headless::run(&options, frames, &mut on_frame));
+        if let Err(e) = &stats {
+            error!("{:?} failed, moving on to the next backend: {}", backend, e);
+        }
+        results.push((format!("{:?}", backend), stats));
+    }
+
+    let rows: Vec<_> = results
+        .iter()
+        .map(|(backend, stats)| {
+            let samples = stats.as_ref().map(LatencyStats::samples);
+            (backend.clone(), samples.map_err(String::as_str))
+        })
+        .collect();
+    stats::print_comparison("Backend comparison:", "backend", &rows);
+}
@@ -0,0 +1,739 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
+use log::warn;
+use serde::Serialize;
+use wgpu::{
+    Backends, Color, CompositeAlphaMode, Dx12Compiler, Features, Gles3MinorVersion,
+    PowerPreference, PresentMode, TextureFormat,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::color::{self, HexColor, DEFAULT_CLEAR_COLOR};
+use crate::stats::Thresholds;
+use crate::triangle::DEFAULT_SHADER;
+
+/// Prepended to an option's name to get the environment variable it's read from.
+const ENV_PREFIX: &str = "WBRE_";
+
+/// Command line options for the repro.
+#[derive(Parser, Debug, Clone)]
+#[command(version, about)]
+pub struct Config {
+    /// Present mode to configure the surface with
+    #[arg(long, value_enum, default_value_t = PresentModeArg::AutoVsync)]
+    pub present_mode: PresentModeArg,
+
+    /// Surface format to use instead of the first sRGB one the surface supports
+    #[arg(long, value_enum)]
+    pub format: Option<FormatArg>,
+
+    /// Surface alpha mode to use instead of the first one the surface supports
+    #[arg(long, value_enum)]
+    pub alpha_mode: Option<AlphaModeArg>,
+
+    /// Prefer a linear surface format over an sRGB one. The clear color is
+    /// adjusted so it looks the same either way
+    #[arg(long)]
+    pub no_srgb: bool,
+
+    /// How many frames the surface may queue up ahead of the GPU. This is what
+    /// decides how long acquiring the surface texture can block
+    #[arg(long, value_name = "FRAMES", default_value_t = 2)]
+    pub frame_latency: u32,
+
+    /// Count acquiring the surface texture as over the timeout when it takes
+    /// longer than this many milliseconds. Each one logs a warning, 5 in a row
+    /// log errors and 20 in a row a SUSTAINED STALL. 0 logs every frame instead
+    #[arg(long, env = "BLOCK_TIMEOUT_MS", default_value_t = 500)]
+    pub block_timeout_ms: u64,
+
+    /// Log a warning when acquiring the surface texture takes longer than this
+    /// many milliseconds
+    #[arg(long, env = "WARN_TIMEOUT_MS", default_value_t = 100)]
+    pub warn_timeout_ms: u64,
+
+    /// Leave this many frames at the start out of the latency summary, so
+    /// shader compilation and other startup costs don't skew it
+    #[arg(long, value_name = "FRAMES", default_value_t = 10)]
+    pub warmup_frames: u32,
+
+    /// Number of recent frames the once-per-second moving average of acquire times covers
+    #[arg(long, value_name = "FRAMES", default_value_t = 120, value_parser = clap::value_parser!(u32).range(1..))]
+    pub window_size: u32,
+
+    /// Warn when the time between two presents differs from a whole number of
+    /// the monitor's refresh intervals by more than this many percent of one.
+    /// Only checked with vsync
+    #[arg(long, value_name = "PERCENT", default_value_t = 20.0)]
+    pub pacing_tolerance: f64,
+
+    /// Log a warning when handling a single event loop iteration, outside of
+    /// rendering, takes longer than this many milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 5)]
+    pub callback_warn_ms: u64,
+
+    /// Write per-frame timings to this CSV file
+    #[arg(long, value_name = "PATH")]
+    pub csv: Option<PathBuf>,
+
+    /// Write per-frame timings as JSON lines to this file, or stdout for `-`
+    #[arg(long, value_name = "PATH")]
+    pub json_metrics: Option<PathBuf>,
+
+    /// Send a `frame,window,acquire_us` UDP packet per frame to this HOST:PORT
+    #[arg(long, value_name = "HOST:PORT")]
+    pub metrics_addr: Option<String>,
+
+    /// Write every window resize to this CSV file, for replaying with --replay
+    #[arg(long, value_name = "PATH")]
+    pub record_resizes: Option<PathBuf>,
+
+    /// Replay the resizes from a --record-resizes file on the same timeline,
+    /// against windows that can't be resized by hand
+    #[arg(long, value_name = "PATH", conflicts_with = "record_resizes")]
+    pub replay: Option<PathBuf>,
+
+    /// Restrict wgpu to a single backend
+    #[arg(long, value_enum, default_value_t = BackendArg::All)]
+    pub backend: BackendArg,
+
+    /// Force the backend's validation layers and debug labels on, even in
+    /// release builds
+    #[arg(long)]
+    pub validate: bool,
+
+    /// OpenGL ES 3 minor version to request from the GL backend
+    #[arg(long, value_enum, default_value_t = GlesMinorArg::Auto)]
+    pub gles_minor: GlesMinorArg,
+
+    /// Shader compiler for the DX12 backend. DXC needs dxcompiler.dll and
+    /// dxil.dll next to the executable or on the library path
+    #[arg(long, value_enum, default_value_t = Dx12CompilerArg::Fxc)]
+    pub dx12_compiler: Dx12CompilerArg,
+
+    /// Which kind of GPU to prefer when requesting an adapter, e.g. the
+    /// integrated or discrete one on a laptop
+    #[arg(long, value_enum, default_value_t = PowerArg::None)]
+    pub power: PowerArg,
+
+    /// Print the settings resolved for each window as JSON and exit without rendering
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Print the available adapters and exit
+    #[arg(long)]
+    pub list_adapters: bool,
+
+    /// Print every format, present mode, alpha mode and usage the window's
+    /// surface supports, then exit
+    #[arg(long, conflicts_with = "headless")]
+    pub dump_caps: bool,
+
+    /// Use the adapter at this index from --list-adapters instead of letting wgpu choose
+    #[arg(long, value_name = "INDEX")]
+    pub adapter: Option<usize>,
+
+    /// Use the first adapter whose name contains this, ignoring case. More
+    /// stable across runs than --adapter
+    #[arg(long, value_name = "SUBSTRING", conflicts_with = "adapter")]
+    pub adapter_name: Option<String>,
+
+    /// Use the software fallback adapter, such as lavapipe or WARP. If a stall
+    /// goes away with this, the driver is the likely culprit
+    #[arg(long)]
+    pub fallback_adapter: bool,
+
+    /// Request this device feature, such as POLYGON_MODE_LINE. Can be repeated
+    #[arg(long = "feature", value_name = "NAME", value_parser = parse_feature)]
+    pub features: Vec<Features>,
+
+    /// Request the adapter's best limits instead of the defaults
+    #[arg(long)]
+    pub max_limits: bool,
+
+    /// Format of the log output
+    #[arg(long, value_enum, default_value_t = LogFormatArg::Text)]
+    pub log_format: LogFormatArg,
+
+    /// Log extra detail such as the full adapter limits
+    #[arg(long)]
+    pub verbose: bool,
+
+    /// Log how long each phase of startup takes, from creating the window to
+    /// configuring the surface
+    #[arg(long)]
+    pub profile_startup: bool,
+
+    /// Cap the frame rate to this many frames per second
+    #[arg(long, value_name = "FPS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_fps: Option<u32>,
+
+    /// Pace frames with a CPU timer at this many frames per second instead of
+    /// relying on the present mode. Combine with --present-mode immediate to
+    /// see whether CPU-side pacing avoids the driver blocking
+    #[arg(long, value_name = "HZ", conflicts_with_all = ["max_fps", "uncapped"], value_parser = clap::value_parser!(u32).range(1..))]
+    pub software_vsync: Option<u32>,
+
+    /// Measure every present mode the surface supports for this many seconds
+    /// each, then print a comparison and exit
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub sweep_present_modes: Option<Duration>,
+
+    /// Measure every maximum frame latency from 1 up to this one in turn, then
+    /// print a comparison of acquire times and exit
+    #[arg(long, value_name = "MAX", conflicts_with_all = ["headless", "sweep_present_modes", "frame_latency"], value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_frame_latency_sweep: Option<u32>,
+
+    /// Seconds spent on each frame latency by --max-frame-latency-sweep
+    #[arg(long, value_name = "SECONDS", default_value = "5", value_parser = parse_seconds)]
+    pub latency_sweep_interval: Duration,
+
+    /// Render as fast as possible: Immediate present mode (or Mailbox where
+    /// that's unsupported) with no frame rate cap. Overrides --present-mode and --max-fps
+    #[arg(long)]
+    pub uncapped: bool,
+
+    /// Preset for double buffering: Fifo present mode with a frame latency of 1
+    #[arg(long, conflicts_with_all = ["triple_buffer", "present_mode", "frame_latency", "uncapped", "headless", "sweep_present_modes", "max_frame_latency_sweep"])]
+    pub double_buffer: bool,
+
+    /// Preset for triple buffering: Mailbox present mode (vsync where that's
+    /// unsupported) with a frame latency of 2
+    #[arg(long, conflicts_with_all = ["present_mode", "frame_latency", "uncapped", "headless", "sweep_present_modes", "max_frame_latency_sweep"])]
+    pub triple_buffer: bool,
+
+    /// Spin the CPU for this many milliseconds before rendering each frame, to
+    /// reproduce stalls that need CPU contention
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub cpu_busy_ms: u64,
+
+    /// Sleep this many milliseconds between submitting a frame and presenting
+    /// it, to simulate the present arriving late relative to vsync
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub present_delay_ms: u64,
+
+    /// Render this many frames into an off-screen texture without opening a window, then exit
+    #[arg(long, value_name = "FRAMES")]
+    pub headless: Option<u64>,
+
+    /// Render this many headless frames on each backend in turn, then print a
+    /// table comparing them and exit
+    #[arg(long, value_name = "FRAMES", conflicts_with_all = ["headless", "backend"])]
+    pub compare_backends: Option<u64>,
+
+    /// Render into a texture of our own and copy it to the surface texture
+    /// before presenting, instead of rendering to the surface directly
+    #[arg(long)]
+    pub offscreen_then_blit: bool,
+
+    /// Render with a depth buffer attached
+    #[arg(long)]
+    pub depth: bool,
+
+    /// Clear color as RRGGBB or RRGGBBAA hex
+    #[arg(long, value_name = "HEX", value_parser = HexColor::parse)]
+    pub clear_color: Option<HexColor>,
+
+    /// Clear to a different random color every frame, so a display that stopped
+    /// updating shows a frozen color even while frames are logged
+    #[arg(long, conflicts_with = "clear_color")]
+    pub animate_color: bool,
+
+    /// Put the frame number in the encoder and render pass labels, so frames
+    /// can be told apart in RenderDoc or PIX captures
+    #[arg(long)]
+    pub label_frames: bool,
+
+    /// Seed for the --animate-color sequence, which is the same in every run
+    /// with the same seed
+    #[arg(long, default_value_t = 0, requires = "animate_color")]
+    pub seed: u64,
+
+    /// Draw the triangle with the WGSL shader in this file instead of the
+    /// built-in one. It needs `vs_main` and `fs_main` entry points
+    #[arg(long, value_name = "PATH")]
+    pub shader: Option<PathBuf>,
+
+    /// MSAA sample count
+    #[arg(long, default_value_t = 1, value_parser = parse_sample_count)]
+    pub msaa: u32,
+
+    /// Number of render passes recorded into each frame's encoder. The first
+    /// clears and the rest draw on top, so GPU work grows with the count
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub passes: u32,
+
+    /// Report frames that block for longer than this many seconds from a
+    /// background thread. 0 disables the watchdog
+    #[arg(long, value_name = "SECONDS", default_value_t = 5)]
+    pub watchdog_secs: u64,
+
+    /// Abort the process when the watchdog fires, to get a core dump
+    #[arg(long)]
+    pub watchdog_abort: bool,
+
+    /// Initial inner size of each window, or the size of the headless target,
+    /// in physical pixels as WIDTHxHEIGHT
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size)]
+    pub size: Option<PhysicalSize<u32>>,
+
+    /// Render at this fixed size in physical pixels as WIDTHxHEIGHT and scale
+    /// the result up to the window, whatever size it's resized to
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_size, conflicts_with_all = ["headless", "offscreen_then_blit"])]
+    pub internal_res: Option<PhysicalSize<u32>>,
+
+    /// Draw into the largest W:H region centered in the window, leaving the
+    /// rest of it cleared, to reproduce stalls that depend on the aspect ratio
+    #[arg(long, value_name = "W:H", value_parser = parse_aspect)]
+    pub aspect: Option<AspectRatio>,
+
+    /// Number of windows to render to, all sharing one device and queue
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub windows: u32,
+
+    /// Exit after rendering for this many seconds. With --headless, stops at
+    /// whichever of the two limits is reached first
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub run_for: Option<Duration>,
+
+    /// Log how many textures, buffers and other resources wgpu is holding on
+    /// to every this many seconds, to spot resources that are never freed
+    #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+    pub report_mem: Option<Duration>,
+
+    /// Exit after rendering exactly this many frames to each window
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub frames: Option<u64>,
+
+    /// Write a Chrome trace of every frame's acquire, submit and present to
+    /// this file, for viewing in chrome://tracing or Perfetto
+    #[arg(long, value_name = "PATH")]
+    pub trace_chrome: Option<PathBuf>,
+
+    /// Wait for the GPU with an explicit, timed `device.poll` after each submit
+    #[arg(long)]
+    pub poll_wait: bool,
+
+    /// Configure the surface again at the start of every frame, timing each
+    /// configure, to measure what doing so by accident costs
+    #[arg(long, conflicts_with = "headless")]
+    pub reconfigure_each_frame: bool,
+
+    /// Render and submit from a dedicated thread while the main thread only
+    /// runs the event loop, the way many engines are structured
+    #[arg(long, conflicts_with_all = ["headless", "max_fps", "software_vsync", "replay", "record_resizes"])]
+    pub render_thread: bool,
+
+    /// Acquire and present every frame without drawing anything, submitting an
+    /// empty command buffer instead, to rule out GPU work as the cause of stalls
+    #[arg(long, conflicts_with_all = ["headless", "gpu_timing"])]
+    pub no_render: bool,
+
+    /// Stop presenting after this many frames but keep acquiring, to show
+    /// acquires blocking once the swapchain runs out of images
+    #[arg(long, value_name = "FRAMES")]
+    pub starve_after: Option<u64>,
+
+    /// Smoke test: render a single frame, then exit with a non-zero status if
+    /// acquiring the surface texture failed
+    #[arg(long, conflicts_with = "frames")]
+    pub once: bool,
+
+    /// Log how long the render pass takes on the GPU each frame, using
+    /// timestamp queries. Waits for the GPU to finish every frame
+    #[arg(long)]
+    pub gpu_timing: bool,
+}
+
+impl Config {
+    /// Parses the command line, reading every option not given there from an
+    /// environment variable named after it, e.g. `WBRE_PRESENT_MODE`, then
+    /// expands shortcut flags into the options they stand for. Options that
+    /// already had an unprefixed variable still read it unless the prefixed
+    /// one is set.
+    pub fn from_env_and_args() -> Config {
+        let command = Config::command().mut_args(|arg| {
+            let id = arg.get_id().as_str();
+            if matches!(id, "help" | "version") {
+                return arg;
+            }
+            let name = format!("{}{}", ENV_PREFIX, id.to_uppercase());
+            if arg.get_env().is_some() && env::var_os(&name).is_none() {
+                return arg;
+            }
+            arg.env(name)
+        });
+        Config::from_arg_matches(&command.get_matches())
+            .unwrap_or_else(|e| e.exit())
+            .expand_shortcuts()
+    }
+
+    /// Sets the options that shortcut flags like `--uncapped`, `--once` and the
+    /// buffering presets stand for. `run` calls this itself, so a `Config`
+    /// built some other way behaves the same.
+    pub fn expand_shortcuts(mut self) -> Config {
+        if self.uncapped {
+            self.present_mode = PresentModeArg::Immediate;
+            self.max_fps = None;
+        }
+        if self.double_buffer {
+            self.present_mode = PresentModeArg::Fifo;
+            self.frame_latency = 1;
+        }
+        if self.triple_buffer {
+            self.present_mode = PresentModeArg::Mailbox;
+            self.frame_latency = 2;
+        }
+        if self.once {
+            self.frames = Some(1);
+        }
+        self
+    }
+
+    /// The name of the buffering preset in use, if any.
+    pub fn buffering_preset(&self) -> Option<&'static str> {
+        if self.double_buffer {
+            Some("--double-buffer")
+        } else if self.triple_buffer {
+            Some("--triple-buffer")
+        } else {
+            None
+        }
+    }
+
+    /// How many frames to leave out of the summary for a run of `frames`.
+    /// A run no longer than the warmup has none left out, so it still has a summary.
+    pub fn warmup_for(&self, frames: Option<u64>) -> u32 {
+        match frames {
+            Some(frames) if self.warmup_frames > 0 && frames <= self.warmup_frames as u64 => {
+                warn!(
+                    "Only {} frames will be rendered, no more than the {} warmup frames, so none are left out of the summary",
+                    frames, self.warmup_frames
+                );
+                0
+            }
+            _ => self.warmup_frames,
+        }
+    }
+
+    pub fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            soft: Duration::from_millis(self.warn_timeout_ms),
+            hard: Duration::from_millis(self.block_timeout_ms),
+        }
+    }
+
+    /// The WGSL source from `--shader`, or the built-in shader. Fails if the file can't be read.
+    pub fn shader_source(&self) -> Result<String, String> {
+        let Some(path) = &self.shader else {
+            return Ok(DEFAULT_SHADER.to_string());
+        };
+        fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read shader {}: {}", path.display(), e))
+    }
+
+    pub fn callback_warn(&self) -> Duration {
+        Duration::from_millis(self.callback_warn_ms)
+    }
+
+    pub fn cpu_busy(&self) -> Duration {
+        Duration::from_millis(self.cpu_busy_ms)
+    }
+
+    pub fn clear_color(&self, format: TextureFormat) -> Color {
+        match self.clear_color {
+            Some(color) => color.to_color(format),
+            // The default was picked on an sRGB target, so encode it to look the same elsewhere.
+            None if !format.is_srgb() => color::linear_to_srgb(DEFAULT_CLEAR_COLOR),
+            None => DEFAULT_CLEAR_COLOR,
+        }
+    }
+}
+
+/// A width to height ratio, e.g. 16:9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AspectRatio {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AspectRatio {
+    /// The largest region with this ratio centered in a `width` by `height`
+    /// target, as x, y, width and height.
+    pub fn letterbox(&self, width: u32, height: u32) -> (f32, f32, f32, f32) {
+        let (width, height) = (width as f32, height as f32);
+        let ratio = self.width as f32 / self.height as f32;
+        if width / height > ratio {
+            let inner = height * ratio;
+            ((width - inner) / 2.0, 0.0, inner, height)
+        } else {
+            let inner = width / ratio;
+            (0.0, (height - inner) / 2.0, width, inner)
+        }
+    }
+}
+
+/// The settings a window actually ended up with once the adapter and surface
+/// were set up, for pasting into bug reports.
+#[derive(Serialize)]
+pub struct EffectiveConfig {
+    pub backend: String,
+    pub adapter: String,
+    pub format: String,
+    pub present_mode: String,
+    pub alpha_mode: String,
+    pub frame_latency: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("expected a non-negative number of seconds, got {:?}", s))
+}
+
+fn parse_size(s: &str) -> Result<PhysicalSize<u32>, String> {
+    s.split_once('x')
+        .and_then(|(width, height)| {
+            Some(PhysicalSize::new(width.parse().ok()?, height.parse().ok()?))
+        })
+        .filter(|size| size.width > 0 && size.height > 0)
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT such as 1920x1080, got {:?}", s))
+}
+
+fn parse_aspect(s: &str) -> Result<AspectRatio, String> {
+    s.split_once(':')
+        .and_then(|(width, height)| {
+            Some(AspectRatio {
+                width: width.parse().ok()?,
+                height: height.parse().ok()?,
+            })
+        })
+        .filter(|aspect| aspect.width > 0 && aspect.height > 0)
+        .ok_or_else(|| format!("expected W:H such as 16:9, got {:?}", s))
+}
+
+fn parse_feature(s: &str) -> Result<Features, String> {
+    Features::from_name(&s.to_uppercase().replace('-', "_"))
+        .ok_or_else(|| format!("unknown feature {:?}", s))
+}
+
+fn parse_sample_count(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(count @ (1 | 2 | 4 | 8)) => Ok(count),
+        _ => Err(format!("expected 1, 2, 4 or 8, got {:?}", s)),
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentModeArg {
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate,
+    AutoVsync,
+    AutoNoVsync,
+}
+
+impl From<PresentModeArg> for PresentMode {
+    fn from(mode: PresentModeArg) -> Self {
+        match mode {
+            PresentModeArg::Fifo => PresentMode::Fifo,
+            PresentModeArg::FifoRelaxed => PresentMode::FifoRelaxed,
+            PresentModeArg::Mailbox => PresentMode::Mailbox,
+            PresentModeArg::Immediate => PresentMode::Immediate,
+            PresentModeArg::AutoVsync => PresentMode::AutoVsync,
+            PresentModeArg::AutoNoVsync => PresentMode::AutoNoVsync,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormatArg {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per record with timestamp, level, target and message
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaModeArg {
+    Opaque,
+    PreMultiplied,
+    PostMultiplied,
+    Inherit,
+}
+
+impl From<AlphaModeArg> for CompositeAlphaMode {
+    fn from(mode: AlphaModeArg) -> Self {
+        match mode {
+            AlphaModeArg::Opaque => CompositeAlphaMode::Opaque,
+            AlphaModeArg::PreMultiplied => CompositeAlphaMode::PreMultiplied,
+            AlphaModeArg::PostMultiplied => CompositeAlphaMode::PostMultiplied,
+            AlphaModeArg::Inherit => CompositeAlphaMode::Inherit,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatArg {
+    #[value(name = "bgra8unorm")]
+    Bgra8Unorm,
+    #[value(name = "bgra8unorm-srgb")]
+    Bgra8UnormSrgb,
+    #[value(name = "rgba8unorm")]
+    Rgba8Unorm,
+    #[value(name = "rgba8unorm-srgb")]
+    Rgba8UnormSrgb,
+    #[value(name = "rgb10a2unorm")]
+    Rgb10a2Unorm,
+    #[value(name = "rgba16float")]
+    Rgba16Float,
+}
+
+impl From<FormatArg> for TextureFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Bgra8Unorm => TextureFormat::Bgra8Unorm,
+            FormatArg::Bgra8UnormSrgb => TextureFormat::Bgra8UnormSrgb,
+            FormatArg::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+            FormatArg::Rgba8UnormSrgb => TextureFormat::Rgba8UnormSrgb,
+            FormatArg::Rgb10a2Unorm => TextureFormat::Rgb10a2Unorm,
+            FormatArg::Rgba16Float => TextureFormat::Rgba16Float,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendArg {
+    Vulkan,
+    Dx12,
+    Metal,
+    Gl,
+    All,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlesMinorArg {
+    Auto,
+    #[value(name = "0")]
+    Version0,
+    #[value(name = "1")]
+    Version1,
+    #[value(name = "2")]
+    Version2,
+}
+
+impl From<GlesMinorArg> for Gles3MinorVersion {
+    fn from(version: GlesMinorArg) -> Self {
+        match version {
+            GlesMinorArg::Auto => Gles3MinorVersion::Automatic,
+            GlesMinorArg::Version0 => Gles3MinorVersion::Version0,
+            GlesMinorArg::Version1 => Gles3MinorVersion::Version1,
+            GlesMinorArg::Version2 => Gles3MinorVersion::Version2,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dx12CompilerArg {
+    Fxc,
+    Dxc,
+}
+
+impl From<Dx12CompilerArg> for Dx12Compiler {
+    fn from(compiler: Dx12CompilerArg) -> Self {
+        match compiler {
+            Dx12CompilerArg::Fxc => Dx12Compiler::Fxc,
+            // Load the DLLs by the platform's usual rules.
+            Dx12CompilerArg::Dxc => Dx12Compiler::Dxc {
+                dxil_path: None,
+                dxc_path: None,
+            },
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerArg {
+    Low,
+    High,
+    None,
+}
+
+impl From<PowerArg> for PowerPreference {
+    fn from(power: PowerArg) -> Self {
+        match power {
+            PowerArg::Low => PowerPreference::LowPower,
+            PowerArg::High => PowerPreference::HighPerformance,
+            PowerArg::None => PowerPreference::None,
+        }
+    }
+}
+
+impl From<BackendArg> for Backends {
+    fn from(backend: BackendArg) -> Self {
+        match backend {
+            BackendArg::Vulkan => Backends::VULKAN,
+            BackendArg::Dx12 => Backends::DX12,
+            BackendArg::Metal => Backends::METAL,
+            BackendArg::Gl => Backends::GL,
+            BackendArg::All => Backends::all(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1920x1080"), Ok(PhysicalSize::new(1920, 1080)));
+        assert_eq!(parse_size("1x1"), Ok(PhysicalSize::new(1, 1)));
+    }
+
+    #[test]
+    fn rejects_malformed_sizes() {
+        for s in [
+            "",
+            "1920",
+            "1920x",
+            "x1080",
+            "0x1080",
+            "1920x0",
+            "-1x1080",
+            "1920X1080",
+            "1920x1080x1",
+            "widexhigh",
+        ] {
+            assert!(parse_size(s).is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn parses_aspect_ratios() {
+        assert_eq!(
+            parse_aspect("16:9"),
+            Ok(AspectRatio {
+                width: 16,
+                height: 9
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_aspect_ratios() {
+        for s in [
+            "", "16", "16:", ":9", "16:0", "0:9", "16/9", "16:9:1", "-16:9",
+        ] {
+            assert!(parse_aspect(s).is_err(), "{:?} parsed", s);
+        }
+    }
+}
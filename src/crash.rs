@@ -0,0 +1,73 @@
+use std::panic;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use log::error;
+use wgpu::{AdapterInfo, PresentMode, TextureFormat};
+
+/// What the GPU was set up as, kept up to date for the panic hook.
+struct Context {
+    adapter: Option<String>,
+    backend: Option<wgpu::Backend>,
+    format: Option<TextureFormat>,
+    present_mode: Option<PresentMode>,
+}
+
+static CONTEXT: Mutex<Context> = Mutex::new(Context {
+    adapter: None,
+    backend: None,
+    format: None,
+    present_mode: None,
+});
+static FRAME: AtomicU64 = AtomicU64::new(0);
+
+/// Makes panics log the adapter, surface setup and frame they happened on
+/// before the default panic message is printed.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        log_context();
+        default_hook(info);
+    }));
+}
+
+pub fn set_adapter(info: &AdapterInfo) {
+    let mut context = CONTEXT.lock().unwrap();
+    context.adapter = Some(info.name.clone());
+    context.backend = Some(info.backend);
+}
+
+pub fn set_format(format: TextureFormat) {
+    CONTEXT.lock().unwrap().format = Some(format);
+}
+
+pub fn set_present_mode(present_mode: PresentMode) {
+    CONTEXT.lock().unwrap().present_mode = Some(present_mode);
+}
+
+pub fn set_frame(frame: u64) {
+    FRAME.store(frame, Ordering::Relaxed);
+}
+
+fn log_context() {
+    let frame = FRAME.load(Ordering::Relaxed);
+    // The panic may have happened while the context was locked, so don't wait for it.
+    let Ok(context) = CONTEXT.try_lock() else {
+        error!("Panicked on frame {}", frame);
+        return;
+    };
+    error!(
+        "Panicked on frame {} using adapter {} on {} backend, format {}, present mode {}",
+        frame,
+        context.adapter.as_deref().unwrap_or("(none)"),
+        context
+            .backend
+            .map_or("(none)".to_string(), |backend| format!("{:?}", backend)),
+        context
+            .format
+            .map_or("(none)".to_string(), |format| format!("{:?}", format)),
+        context
+            .present_mode
+            .map_or("(none)".to_string(), |mode| format!("{:?}", mode)),
+    );
+}
@@ -0,0 +1,373 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use wgpu::{
+    Adapter, AdapterInfo, Device, DeviceDescriptor, DeviceLostReason, DeviceType, ErrorFilter,
+    Features, Instance, InstanceDescriptor, InstanceFlags, Limits, Queue, RequestAdapterOptions,
+    Surface,
+};
+
+use crate::config::{Config, PowerArg};
+use crate::crash;
+use crate::startup;
+
+/// How many times adapter and device requests are tried before giving up.
+const REQUEST_ATTEMPTS: u32 = 4;
+/// How long to wait before the first retry, doubled for every retry after.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The adapter, device and queue shared by everything that renders.
+pub struct Gpu {
+    pub adapter: Adapter,
+    pub device: Device,
+    pub queue: Queue,
+    /// Set from the device lost callback, which may run on any thread.
+    lost: Arc<AtomicBool>,
+}
+
+impl Gpu {
+    /// Selects an adapter able to present to `compatible_surface`, if given,
+    /// and opens a device on it. Fails if no adapter fits or it refuses the device.
+    pub async fn new(
+        instance: &Instance,
+        compatible_surface: Option<&Surface<'_>>,
+        options: &Config,
+    ) -> Result<Gpu, String> {
+        let phase_start = Instant::now();
+        let adapter = select_adapter(instance, compatible_surface, options).await?;
+        startup::phase_done(options, "adapter request", phase_start);
+        log_adapter_info(&adapter, options.verbose);
+        let phase_start = Instant::now();
+        let (device, queue) = request_device(&adapter, options).await?;
+        startup::phase_done(options, "device request", phase_start);
+
+        let lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            // Dropping the device or replacing the callback also invokes it,
+            // neither of which means the GPU went away.
+            if matches!(
+                reason,
+                DeviceLostReason::Dropped | DeviceLostReason::ReplacedCallback
+            ) {
+                return;
+            }
+            error!("Device lost ({:?}): {}", reason, message);
+            lost_flag.store(true, Ordering::Relaxed);
+        });
+
+        Ok(Self {
+            adapter,
+            device,
+            queue,
+            lost,
+        })
+    }
+
+    /// Whether the device was lost, e.g. to a GPU reset, and has to be recreated.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Gpu {
+    fn drop(&mut self) {
+        // Some backends report dropping the device as an unknown loss, so stop
+        // listening before the device goes away.
+        self.device.set_device_lost_callback(|_, _| {});
+    }
+}
+
+/// Captures validation errors from whatever is created while it's open, so a
+/// failure can name the resource instead of ending in wgpu's default panic.
+pub struct ErrorScope<'a> {
+    device: &'a Device,
+    what: &'a str,
+}
+
+impl<'a> ErrorScope<'a> {
+    /// `what` names the resources being created, for the error message.
+    pub fn push(device: &'a Device, what: &'a str) -> ErrorScope<'a> {
+        device.push_error_scope(ErrorFilter::Validation);
+        Self { device, what }
+    }
+
+    /// Fails if anything created since `push` failed validation.
+    pub fn finish(self) -> Result<(), String> {
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(e) => Err(format!("Failed to create {}: {}", self.what, e)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub fn create_instance(options: &Config) -> Instance {
+    let flags = if options.validate {
+        info!("Validation layers are enabled, which slows down and may serialize GPU work");
+        InstanceFlags::default() | InstanceFlags::VALIDATION | InstanceFlags::DEBUG
+    } else {
+        InstanceFlags::default()
+    };
+    let descriptor = InstanceDescriptor {
+        backends: options.backend.into(),
+        flags,
+        dx12_shader_compiler: options.dx12_compiler.into(),
+        gles_minor_version: options.gles_minor.into(),
+    };
+    info!(
+        "Requesting GLES minor version {:?} and the {:?} DX12 shader compiler",
+        descriptor.gles_minor_version, descriptor.dx12_shader_compiler
+    );
+    Instance::new(descriptor)
+}
+
+/// Prints every adapter the instance can see, with the index `--adapter` expects.
+pub fn list_adapters(instance: &Instance, options: &Config) {
+    for (index, adapter) in instance
+        .enumerate_adapters(options.backend.into())
+        .iter()
+        .enumerate()
+    {
+        let info = adapter.get_info();
+        println!(
+            "{}: {} ({:?}, {:?})",
+            index, info.name, info.backend, info.device_type
+        );
+    }
+}
+
+/// Prints everything `surface` supports on `adapter`, for bug reports.
+pub fn print_surface_capabilities(surface: &Surface<'_>, adapter: &Adapter) {
+    let caps = surface.get_capabilities(adapter);
+    println!("Surface capabilities on {}:", adapter.get_info().name);
+    println!("  formats:       {:?}", caps.formats);
+    println!("  present modes: {:?}", caps.present_modes);
+    println!("  alpha modes:   {:?}", caps.alpha_modes);
+    println!("  usages:        {:?}", caps.usages);
+}
+
+/// Picks the adapter given by `--adapter`, or lets wgpu choose one for the
+/// surface. Headless runs pass no surface.
+async fn select_adapter(
+    instance: &Instance,
+    surface: Option<&Surface<'_>>,
+    options: &Config,
+) -> Result<Adapter, String> {
+    if let Some(name) = &options.adapter_name {
+        let adapter = select_adapter_by_name(instance, name, options)?;
+        warn_if_surface_unsupported(&adapter, surface);
+        return Ok(adapter);
+    }
+
+    let Some(index) = options.adapter else {
+        let adapter = retry("Requesting an adapter", || async {
+            instance
+                .request_adapter(&RequestAdapterOptions {
+                    power_preference: options.power.into(),
+                    compatible_surface: surface,
+                    force_fallback_adapter: options.fallback_adapter,
+                })
+                .await
+                .ok_or("no suitable adapter was found")
+        })
+        .await;
+        return match adapter {
+            Ok(adapter) => {
+                if options.fallback_adapter {
+                    info!("Using the fallback adapter {}", adapter.get_info().name);
+                }
+                if options.power != PowerArg::None {
+                    info!(
+                        "Power preference {:?} selected adapter {}",
+                        options.power,
+                        adapter.get_info().name
+                    );
+                }
+                Ok(adapter)
+            }
+            Err(_) if options.fallback_adapter => {
+                Err("No fallback adapter is available on this system".to_string())
+            }
+            Err(e) => Err(format!("No adapter is available: {}", e)),
+        };
+    };
+
+    if options.fallback_adapter {
+        warn!("--fallback-adapter is ignored when --adapter picks one explicitly");
+    }
+    if options.power != PowerArg::None {
+        warn!("--power is ignored when --adapter picks one explicitly");
+    }
+
+    let mut adapters = instance.enumerate_adapters(options.backend.into());
+    if index >= adapters.len() {
+        return Err(format!(
+            "Adapter index {} is out of range, only {} adapters are available (see --list-adapters)",
+            index,
+            adapters.len()
+        ));
+    }
+
+    let adapter = adapters.swap_remove(index);
+    warn_if_surface_unsupported(&adapter, surface);
+    Ok(adapter)
+}
+
+/// Adapters picked by hand aren't checked against the surface, so warn instead.
+fn warn_if_surface_unsupported(adapter: &Adapter, surface: Option<&Surface<'_>>) {
+    if let Some(surface) = surface {
+        if !adapter.is_surface_supported(surface) {
+            warn!(
+                "Adapter {} does not report support for this surface",
+                adapter.get_info().name
+            );
+        }
+    }
+}
+
+fn select_adapter_by_name(
+    instance: &Instance,
+    name: &str,
+    options: &Config,
+) -> Result<Adapter, String> {
+    let needle = name.to_lowercase();
+    let adapters = instance.enumerate_adapters(options.backend.into());
+    let names: Vec<_> = adapters
+        .iter()
+        .map(|adapter| adapter.get_info().name)
+        .collect();
+    match names
+        .iter()
+        .position(|name| name.to_lowercase().contains(&needle))
+    {
+        Some(index) => Ok(adapters.into_iter().nth(index).unwrap()),
+        None => Err(format!(
+            "No adapter name contains {:?}, available adapters: {:?}",
+            name, names
+        )),
+    }
+}
+
+async fn request_device(adapter: &Adapter, options: &Config) -> Result<(Device, Queue), String> {
+    let requested = options
+        .features
+        .iter()
+        .copied()
+        .fold(Features::empty(), |a, b| a | b);
+    let unsupported = requested - adapter.features();
+    if !unsupported.is_empty() {
+        return Err(format!(
+            "Adapter doesn't support the requested features {:?}",
+            unsupported
+        ));
+    }
+
+    let mut required_features = requested;
+    if options.gpu_timing {
+        if adapter.features().contains(Features::TIMESTAMP_QUERY) {
+            required_features |= Features::TIMESTAMP_QUERY;
+        } else {
+            warn!("Adapter doesn't support timestamp queries, GPU timing is unavailable");
+        }
+    }
+
+    let descriptor = DeviceDescriptor {
+        label: None,
+        required_features,
+        required_limits: if options.max_limits {
+            adapter.limits()
+        } else {
+            Limits::default()
+        },
+    };
+    retry("Requesting a device", || {
+        adapter.request_device(&descriptor, None)
+    })
+    .await
+    .map_err(|e| format!("Failed to create a device: {}", e))
+}
+
+/// Runs `request` until it succeeds or has failed `REQUEST_ATTEMPTS` times,
+/// backing off between attempts. Right after a driver update or on CI the
+/// first request sometimes fails when a later one wouldn't.
+async fn retry<T, E, F>(what: &str, mut request: impl FnMut() -> F) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: Future<Output = Result<T, E>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(e) if attempt < REQUEST_ATTEMPTS => {
+                warn!(
+                    "{} failed ({}), attempt {}/{}, retrying in {:?}",
+                    what, e, attempt, REQUEST_ATTEMPTS, backoff
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Logs what a bug report needs to know about the adapter.
+fn log_adapter_info(adapter: &Adapter, verbose: bool) {
+    let info = adapter.get_info();
+    crash::set_adapter(&info);
+    info!("Using adapter {} on {:?} backend", info.name, info.backend);
+    info!("  vendor:      {:#06x}", info.vendor);
+    info!("  device:      {:#06x}", info.device);
+    info!("  device type: {:?}", info.device_type);
+    info!("  driver:      {}", info.driver);
+    info!("  driver info: {}", info.driver_info);
+    if verbose {
+        info!("  limits: {:#?}", adapter.limits());
+    }
+    warn_if_virtual(&info);
+}
+
+/// Parts of adapter names that software renderers and the GPUs of virtual
+/// machines and remote desktops report, lowercased.
+const VIRTUAL_ADAPTER_NAMES: &[&str] = &[
+    "microsoft basic render",
+    "llvmpipe",
+    "softpipe",
+    "swiftshader",
+    "svga3d",
+    "vmware",
+    "virtualbox",
+    "virgl",
+    "parallels",
+    "hyper-v",
+    "qxl",
+];
+
+/// Warns if the adapter looks like it's a software renderer or belongs to a
+/// VM or remote desktop, where there is often no real vsync to block on.
+fn warn_if_virtual(info: &AdapterInfo) {
+    let name = info.name.to_lowercase();
+    let matched = VIRTUAL_ADAPTER_NAMES
+        .iter()
+        .find(|virtual_name| name.contains(**virtual_name));
+    let reason = match (info.device_type, matched) {
+        (DeviceType::Cpu, _) => "it renders on the CPU".to_string(),
+        (_, Some(virtual_name)) => format!("its name contains {:?}", virtual_name),
+        _ => return,
+    };
+    warn!("==========================================================================");
+    warn!(
+        "Adapter {} looks like a software renderer or a virtual or remote display ({}). \
+         Presentation timing may not reflect real hardware, so stalls seen here may be \
+         artifacts of the VM or remote session rather than the bug",
+        info.name, reason
+    );
+    warn!("==========================================================================");
+}
@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use log::info;
+use tracing::info_span;
+use wgpu::{
+    Extent3d, Maintain, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureViewDescriptor,
+};
+use winit::dpi::PhysicalSize;
+
+use crate::config::Config;
+use crate::crash;
+use crate::gpu::{self, ErrorScope, Gpu};
+use crate::interrupt;
+use crate::memory::MemoryReporter;
+use crate::pacing;
+use crate::scene::{Scene, SceneOptions};
+use crate::stats::{LatencyStats, StallStreak};
+use crate::targets::{self, RenderTargets};
+use crate::timing::GpuTimer;
+use crate::FrameStats;
+
+/// Target size unless `--size` gives another.
+const DEFAULT_SIZE: PhysicalSize<u32> = PhysicalSize::new(1280, 720);
+const FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+/// Used instead of `FORMAT` with `--no-srgb`.
+const LINEAR_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+/// Renders `frames` frames into an off-screen texture without creating a
+/// window, calling `on_frame` after each. Returns the frame times after logging
+/// their summary, or why the device or its resources couldn't be created.
+pub async fn run(
+    options: &Config,
+    frames: u64,
+    mut on_frame: impl FnMut(&FrameStats),
+) -> Result<LatencyStats, String> {
+    let frames = options.frames.map_or(frames, |cap| cap.min(frames));
+    let instance = gpu::create_instance(options);
+    let gpu = Gpu::new(&instance, None, options).await?;
+    let Gpu {
+        adapter,
+        device,
+        queue,
+        ..
+    } = &gpu;
+
+    let PhysicalSize { width, height } = options.size.unwrap_or(DEFAULT_SIZE);
+    let format = if options.no_srgb {
+        LINEAR_FORMAT
+    } else {
+        FORMAT
+    };
+    crash::set_format(format);
+    let scope = ErrorScope::push(device, "the headless target");
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("Headless Target"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&TextureViewDescriptor::default());
+    scope.finish()?;
+    let sample_count =
+        targets::supported_sample_count(adapter, format, options.depth, options.msaa);
+    let targets = RenderTargets::new(
+        device,
+        format,
+        width,
+        height,
+        options.depth,
+        sample_count,
+        options.offscreen_then_blit,
+    )?;
+    let scene = Scene::new(
+        device,
+        &targets,
+        options.clear_color(format),
+        &options.shader_source()?,
+        SceneOptions::new(options),
+    )?;
+    let gpu_timer = options
+        .gpu_timing
+        .then(|| GpuTimer::new(device, queue))
+        .transpose()?
+        .flatten();
+
+    info!(
+        "Rendering {} headless frames at {}x{}",
+        frames, width, height
+    );
+    // Without a surface there's nothing to acquire, so time how long each
+    // frame takes to finish on the GPU instead.
+    let thresholds = options.thresholds();
+    let mut stall_streak = StallStreak::default();
+    let mut stats = LatencyStats::new(
+        "Headless frame".to_string(),
+        options.warmup_for(Some(frames)),
+    );
+    let deadline = options.run_for.map(|duration| Instant::now() + duration);
+    let mut memory = options.report_mem.map(MemoryReporter::new);
+    for frame in 0..frames {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            info!("Run time elapsed, stopping");
+            break;
+        }
+        if interrupt::interrupted() {
+            break;
+        }
+        if let Some(memory) = &mut memory {
+            memory.tick(&instance, adapter);
+        }
+
+        pacing::spin_for(options.cpu_busy());
+
+        let _span = info_span!("frame", frame).entered();
+        crash::set_frame(frame);
+        let timer_start = Instant::now();
+        let mut encoder = scene.create_encoder(device, frame);
+        scene.record(
+            frame,
+            queue,
+            &mut encoder,
+            targets.offscreen().unwrap_or(&view),
+            &targets,
+            gpu_timer.as_ref(),
+        );
+        targets.copy_offscreen(&mut encoder, &texture);
+        let submit_start = Instant::now();
+        info_span!("submit").in_scope(|| queue.submit(std::iter::once(encoder.finish())));
+        let submit_time = submit_start.elapsed();
+        info_span!("wait").in_scope(|| device.poll(Maintain::Wait));
+        let frame_time = timer_start.elapsed();
+
+        if let Some(pass_time) = gpu_timer.as_ref().and_then(|timer| timer.read(device)) {
+            info!("[frame {}] GPU render pass took {:?}", frame, pass_time);
+        }
+
+        let over_timeout = thresholds.check_streak(
+            format_args!("[frame {}] Headless frame", frame),
+            frame_time,
+            &mut stall_streak,
+        );
+        stats.record(frame_time, over_timeout);
+        on_frame(&FrameStats {
+            window: 0,
+            index: frame,
+            acquire: frame_time,
+            submit: submit_time,
+            present: Duration::ZERO,
+            over_timeout,
+        });
+    }
+
+    stats.print_summary();
+    Ok(stats)
+}
@@ -0,0 +1,23 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{error, warn};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Makes Ctrl-C ask the render loop to stop so the summary still gets printed.
+/// A second Ctrl-C exits straight away, in case a frame is blocked for good.
+pub fn install_handler() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        warn!("Interrupted, stopping after this frame. Press Ctrl-C again to exit immediately");
+    });
+    if let Err(e) = result {
+        error!("Failed to install Ctrl-C handler: {}", e);
+    }
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
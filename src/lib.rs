@@ -0,0 +1,659 @@
+mod color;
+mod compare;
+mod config;
+mod crash;
+mod gpu;
+mod headless;
+mod interrupt;
+mod memory;
+mod metrics;
+mod pacing;
+mod render_thread;
+mod resizes;
+mod scene;
+mod screenshot;
+mod startup;
+mod state;
+mod stats;
+mod sweep;
+mod targets;
+mod timing;
+mod triangle;
+mod upscale;
+mod watchdog;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+use wgpu::{Instance, SurfaceError};
+use winit::application::ApplicationHandler;
+use winit::event::{ElementState, KeyEvent, StartCause, WindowEvent};
+use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy};
+use winit::keyboard::PhysicalKey;
+use winit::window::{Window, WindowId};
+
+pub use crate::color::HexColor;
+pub use crate::config::{
+    AlphaModeArg, AspectRatio, BackendArg, Config, Dx12CompilerArg, FormatArg, GlesMinorArg,
+    LogFormatArg, PowerArg, PresentModeArg,
+};
+use crate::gpu::Gpu;
+use crate::memory::MemoryReporter;
+use crate::metrics::{CsvWriter, FrameRecord, JsonWriter, UdpSender};
+use crate::pacing::{FrameLimiter, SoftwareVsync};
+use crate::render_thread::{RenderEvent, RenderThread};
+use crate::resizes::{ResizeEvent, ResizeRecorder, ResizeReplay};
+use crate::state::State;
+
+/// Timings of one rendered frame, passed to the callback given to `run`.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    /// Which window the frame was rendered to, in creation order. Always 0 in
+    /// headless runs.
+    pub window: usize,
+    /// Frame number within that window, counting from zero.
+    pub index: u64,
+    /// Headless runs have nothing to acquire, so there it's how long the
+    /// whole frame took to finish on the GPU instead.
+    pub acquire: Duration,
+    pub submit: Duration,
+    /// Zero in headless runs, which don't present.
+    pub present: Duration,
+    /// Whether acquiring took longer than the hard timeout.
+    pub over_timeout: bool,
+}
+
+impl From<&FrameRecord> for FrameStats {
+    fn from(record: &FrameRecord) -> Self {
+        Self {
+            window: record.window,
+            index: record.index,
+            acquire: record.acquire,
+            submit: record.submit,
+            present: record.present,
+            over_timeout: record.over_timeout,
+        }
+    }
+}
+
+struct App<F> {
+    config: Config,
+    /// Called with every frame rendered to a window.
+    on_frame: F,
+    csv: Option<CsvWriter>,
+    json: Option<JsonWriter>,
+    udp: Option<UdpSender>,
+    limiter: Option<FrameLimiter>,
+    /// Set by `--software-vsync`, in which case frames are requested when its
+    /// timer fires instead of on every event loop iteration.
+    software_vsync: Option<SoftwareVsync>,
+    resize_recorder: Option<ResizeRecorder>,
+    /// Set by `--report-mem`.
+    memory: Option<MemoryReporter>,
+    resize_replay: Option<ResizeReplay>,
+    /// When the windows were created, which resize recordings are timed from.
+    started: Option<Instant>,
+    /// When `--run-for` ends the run, counted from window creation.
+    deadline: Option<Instant>,
+    instance: Option<Instance>,
+    gpu: Option<Arc<Gpu>>,
+    /// One per open window, in creation order. Empty while a render thread owns them.
+    states: Vec<State>,
+    /// Set by `--render-thread` once the windows are created.
+    render_thread: Option<RenderThread>,
+    proxy: EventLoopProxy<RenderEvent>,
+    /// Set when the run should end with a non-zero exit status.
+    failed: bool,
+}
+
+impl<F: FnMut(&FrameStats)> App<F> {
+    fn is_done(&self, state: &State) -> bool {
+        state.is_done(self.config.frames)
+    }
+
+    fn recover_lost_device(&mut self, event_loop: &ActiveEventLoop) {
+        let Some(instance) = &self.instance else {
+            return;
+        };
+        match state::recover_lost_device(&mut self.states, instance, &self.config) {
+            Ok(gpu) => self.gpu = Some(gpu),
+            Err(e) => self.fail(event_loop, &e),
+        }
+    }
+
+    /// Logs why the run can't go on and stops it, making `run` return `false`.
+    fn fail(&mut self, event_loop: &ActiveEventLoop, message: &str) {
+        error!("{}", message);
+        self.failed = true;
+        event_loop.exit();
+    }
+
+    /// Handles whatever is due between event loop iterations and requests the next frames.
+    fn schedule_frames(&mut self, event_loop: &ActiveEventLoop) {
+        if interrupt::interrupted() {
+            event_loop.exit();
+            return;
+        }
+
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            info!("Run time elapsed, exiting");
+            event_loop.exit();
+            return;
+        }
+
+        if self.render_thread.is_some() {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(
+                Instant::now() + render_thread::CHECK_INTERVAL,
+            ));
+            return;
+        }
+
+        if let (Some(replay), Some(started)) = (&mut self.resize_replay, self.started) {
+            let elapsed = started.elapsed();
+            let was_finished = replay.is_finished();
+            while let Some(event) = replay.next_due(elapsed) {
+                match self
+                    .states
+                    .iter_mut()
+                    .find(|state| state.index() == event.window)
+                {
+                    Some(state) => state.request_resize(event.size),
+                    None => warn!(
+                        "Replayed resize is for window {}, which isn't open",
+                        event.window
+                    ),
+                }
+            }
+            if replay.is_finished() && !was_finished {
+                info!("Resize replay finished");
+            }
+        }
+
+        if self.gpu.as_ref().is_some_and(|gpu| gpu.is_lost()) {
+            self.recover_lost_device(event_loop);
+        }
+
+        if let (Some(memory), Some(instance), Some(gpu)) =
+            (&mut self.memory, &self.instance, &self.gpu)
+        {
+            memory.tick(instance, &gpu.adapter);
+        }
+
+        if let Some(limiter) = &self.limiter {
+            if !limiter.is_due(Instant::now()) {
+                event_loop.set_control_flow(ControlFlow::WaitUntil(limiter.next_frame()));
+                return;
+            }
+            event_loop.set_control_flow(ControlFlow::Poll);
+        }
+
+        if let Some(vsync) = &self.software_vsync {
+            event_loop.set_control_flow(ControlFlow::WaitUntil(vsync.next_tick()));
+            return;
+        }
+
+        self.request_redraws();
+    }
+
+    /// Requests a frame from every window that hasn't rendered all it was asked to.
+    fn request_redraws(&self) {
+        for state in &self.states {
+            if !self.is_done(state) {
+                state.window().request_redraw();
+            }
+        }
+    }
+
+    fn write_record(&mut self, record: &FrameRecord) {
+        (self.on_frame)(&FrameStats::from(record));
+        if let Some(csv) = &mut self.csv {
+            if let Err(e) = csv.write(record) {
+                error!("Failed to write CSV row: {}", e);
+            }
+        }
+        if let Some(json) = &mut self.json {
+            if let Err(e) = json.write(record) {
+                error!("Failed to write JSON metrics: {}", e);
+            }
+        }
+        if let Some(udp) = &mut self.udp {
+            if let Err(e) = udp.send(record) {
+                error!("Failed to send metrics packet: {}", e);
+            }
+        }
+    }
+}
+
+impl<F: FnMut(&FrameStats)> ApplicationHandler<RenderEvent> for App<F> {
+    fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+        if !matches!(cause, StartCause::ResumeTimeReached { .. }) {
+            return;
+        }
+        if let Some(vsync) = &mut self.software_vsync {
+            if vsync.tick(Instant::now()) {
+                self.request_redraws();
+            }
+        }
+    }
+
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        // With --render-thread the states live on that thread, so check for
+        // the device instead.
+        if self.gpu.is_some() || self.render_thread.is_some() {
+            return;
+        }
+
+        let startup_start = Instant::now();
+
+        // Set up winit
+        let phase_start = Instant::now();
+        let count = self.config.windows as usize;
+        let mut attributes =
+            Window::default_attributes().with_resizable(self.resize_replay.is_none());
+        if let Some(size) = self.config.size {
+            attributes = attributes.with_inner_size(size);
+        }
+        let windows: Vec<_> = (0..count)
+            .map(|index| {
+                let window = Arc::new(event_loop.create_window(attributes.clone()).unwrap());
+                if count > 1 {
+                    info!("Window {} has id {:?}", index, window.id());
+                }
+                // Some platforms open the window at another size and only send
+                // the requested one in a later resize event.
+                if let Some(size) = self.config.size.filter(|&size| size != window.inner_size()) {
+                    warn!(
+                        "Window {} opened at {}x{} instead of the requested {}x{}",
+                        index,
+                        window.inner_size().width,
+                        window.inner_size().height,
+                        size.width,
+                        size.height
+                    );
+                }
+                window
+            })
+            .collect();
+        startup::phase_done(&self.config, "window creation", phase_start);
+
+        // Set up wgpu, with every window sharing one device
+        let phase_start = Instant::now();
+        let instance = gpu::create_instance(&self.config);
+        startup::phase_done(&self.config, "instance creation", phase_start);
+        let phase_start = Instant::now();
+        let surfaces: Vec<_> = windows
+            .iter()
+            .map(|window| instance.create_surface(window.clone()).unwrap())
+            .collect();
+        startup::phase_done(&self.config, "surface creation", phase_start);
+        let gpu = match pollster::block_on(Gpu::new(&instance, surfaces.first(), &self.config)) {
+            Ok(gpu) => Arc::new(gpu),
+            Err(e) => return self.fail(event_loop, &e),
+        };
+
+        if self.config.dump_caps {
+            for surface in &surfaces {
+                gpu::print_surface_capabilities(surface, &gpu.adapter);
+            }
+            event_loop.exit();
+            return;
+        }
+
+        let phase_start = Instant::now();
+        let states = windows
+            .into_iter()
+            .zip(surfaces)
+            .enumerate()
+            .map(|(index, (window, surface))| {
+                State::new(gpu.clone(), window, surface, index, count > 1, &self.config)
+            })
+            .collect();
+        self.states = match states {
+            Ok(states) => states,
+            Err(e) => return self.fail(event_loop, &e),
+        };
+        startup::phase_done(&self.config, "surface configuration", phase_start);
+        startup::phase_done(&self.config, "everything", startup_start);
+        self.instance = Some(instance);
+        self.gpu = Some(gpu);
+        let now = Instant::now();
+        self.started = Some(now);
+        self.deadline = self.config.run_for.map(|duration| now + duration);
+
+        if self.config.print_config {
+            for state in self.states.drain(..) {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&state.effective_config()).unwrap()
+                );
+            }
+            event_loop.exit();
+        } else if self.config.render_thread {
+            let (Some(gpu), Some(instance)) = (self.gpu.clone(), self.instance.take()) else {
+                return;
+            };
+            self.render_thread = Some(RenderThread::spawn(
+                std::mem::take(&mut self.states),
+                gpu,
+                instance,
+                &self.config,
+                self.proxy.clone(),
+            ));
+        }
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: RenderEvent) {
+        match event {
+            RenderEvent::Frame(record) => {
+                if self.config.once {
+                    info!("Rendered one frame, acquire took {:?}", record.acquire);
+                }
+                self.write_record(&record);
+            }
+            RenderEvent::Finished => {
+                info!("Every window is done rendering, exiting");
+                event_loop.exit();
+            }
+            RenderEvent::SurfaceError(e) if self.config.once => {
+                error!("Surface error {:?} on the only frame", e);
+                self.failed = true;
+                event_loop.exit();
+            }
+            RenderEvent::SurfaceError(SurfaceError::OutOfMemory) => event_loop.exit(),
+            RenderEvent::SurfaceError(e) => error!("Surface error {:?}", e),
+            RenderEvent::Failed(e) => self.fail(event_loop, &e),
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        if let Some(render_thread) = &mut self.render_thread {
+            if render_thread.window_event(window_id, event) {
+                event_loop.exit();
+            }
+            return;
+        }
+
+        let Some(index) = self
+            .states
+            .iter()
+            .position(|state| state.window().id() == window_id)
+        else {
+            return;
+        };
+
+        if event == WindowEvent::CloseRequested {
+            self.states.remove(index).print_summary();
+            if self.states.is_empty() {
+                event_loop.exit();
+            }
+            return;
+        }
+
+        let state = &mut self.states[index];
+        if event != WindowEvent::RedrawRequested {
+            state.note_event(state::event_name(&event));
+        }
+        match event {
+            // A replay owns the surface size, only its events resize it.
+            WindowEvent::Resized(_) if self.resize_replay.is_some() => {}
+            WindowEvent::Resized(size) => {
+                if let (Some(recorder), Some(started)) = (&mut self.resize_recorder, self.started) {
+                    let event = ResizeEvent {
+                        elapsed: started.elapsed(),
+                        window: state.index(),
+                        size,
+                    };
+                    if let Err(e) = recorder.record(&event) {
+                        error!("Failed to record resize: {}", e);
+                    }
+                }
+                state.request_resize(size);
+            }
+            WindowEvent::ScaleFactorChanged { .. } => {
+                state.request_resize(state.window().inner_size());
+            }
+            WindowEvent::Occluded(occluded) => state.set_occluded(occluded),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => state.handle_key(key),
+            WindowEvent::RedrawRequested => {
+                if let Some(limiter) = &mut self.limiter {
+                    limiter.frame_started(Instant::now());
+                }
+
+                if let Err(e) = state.apply_pending_resize() {
+                    return self.fail(event_loop, &e);
+                }
+                if state.is_minimized() {
+                    return;
+                }
+                pacing::spin_for(self.config.cpu_busy());
+                match state.render() {
+                    Ok(None) => {}
+                    Ok(Some(record)) => {
+                        if self.config.once {
+                            info!("Rendered one frame, acquire took {:?}", record.acquire);
+                        }
+                        self.write_record(&record);
+                        if self.states.iter().all(|state| self.is_done(state)) {
+                            info!("Every window is done rendering, exiting");
+                            event_loop.exit();
+                        }
+                    }
+                    Err(e) if self.config.once => {
+                        error!("Surface error {:?} on the only frame", e);
+                        self.failed = true;
+                        event_loop.exit();
+                    }
+                    Err(SurfaceError::Lost) => {
+                        if let Err(e) = state.handle_lost(self.instance.as_ref()) {
+                            self.fail(event_loop, &e);
+                        }
+                    }
+                    Err(SurfaceError::Timeout) => state.record_timeout(),
+                    Err(SurfaceError::Outdated) => {
+                        warn!("Surface outdated, recovering");
+                        state.reconfigure();
+                    }
+                    Err(SurfaceError::OutOfMemory) => event_loop.exit(),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // Time spent here delays the next frame just like a blocking acquire
+        // would, so report it separately to tell the two apart.
+        let callback_start = Instant::now();
+        self.schedule_frames(event_loop);
+        let callback_time = callback_start.elapsed();
+        if callback_time > self.config.callback_warn() {
+            warn!("Event loop callback took {}ms", callback_time.as_millis());
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(render_thread) = self.render_thread.take() {
+            self.states = render_thread.stop();
+        }
+        for state in &self.states {
+            state.print_summary();
+        }
+        if let Some(csv) = &mut self.csv {
+            if let Err(e) = csv.flush() {
+                error!("Failed to flush CSV output: {}", e);
+            }
+        }
+        if let Some(json) = &mut self.json {
+            if let Err(e) = json.flush() {
+                error!("Failed to flush JSON metrics: {}", e);
+            }
+        }
+        if let Some(recorder) = &mut self.resize_recorder {
+            if let Err(e) = recorder.flush() {
+                error!("Failed to flush resize recording: {}", e);
+            }
+        }
+    }
+}
+
+/// Makes Ctrl-C stop the run with a summary, and panics log what the GPU was
+/// doing. Embedders that handle either themselves can leave this out.
+pub fn install_handlers() {
+    interrupt::install_handler();
+    crash::install_hook();
+}
+
+/// Runs whatever `config` asks for. `on_frame` is called after every frame,
+/// headless ones included. The one-off modes like `--list-adapters` don't call it.
+/// Returns `false` if the run failed, e.g. because no adapter fit, a GPU
+/// resource couldn't be created or an output it was asked to write couldn't
+/// be opened. Exiting with a failure code is left to the caller.
+pub fn run(config: Config, mut on_frame: impl FnMut(&FrameStats)) -> bool {
+    let config = config.expand_shortcuts();
+    if config.list_adapters {
+        gpu::list_adapters(&gpu::create_instance(&config), &config);
+        return true;
+    }
+
+    if let Some(frames) = config.compare_backends {
+        compare::compare_backends(&config, frames, &mut on_frame);
+        return true;
+    }
+
+    if let Some(frames) = config.headless {
+        if config.csv.is_some() || config.json_metrics.is_some() || config.metrics_addr.is_some() {
+            warn!("Per-frame metrics record surface timings and are ignored in headless mode");
+        }
+        if let Err(e) = pollster::block_on(headless::run(&config, frames, &mut on_frame)) {
+            error!("{}", e);
+            return false;
+        }
+        return true;
+    }
+
+    let Ok(csv) = config
+        .csv
+        .as_deref()
+        .map(|path| {
+            CsvWriter::create(path).map_err(|e| {
+                error!("Failed to create CSV file {}: {}", path.display(), e);
+            })
+        })
+        .transpose()
+    else {
+        return false;
+    };
+
+    let Ok(json) = config
+        .json_metrics
+        .as_deref()
+        .map(|path| {
+            JsonWriter::create(path).map_err(|e| {
+                error!(
+                    "Failed to create JSON metrics file {}: {}",
+                    path.display(),
+                    e
+                );
+            })
+        })
+        .transpose()
+    else {
+        return false;
+    };
+
+    let Ok(udp) = config
+        .metrics_addr
+        .as_deref()
+        .map(|addr| {
+            UdpSender::connect(addr).map_err(|e| {
+                error!("Failed to set up metrics socket for {}: {}", addr, e);
+            })
+        })
+        .transpose()
+    else {
+        return false;
+    };
+
+    let Ok(resize_recorder) = config
+        .record_resizes
+        .as_deref()
+        .map(|path| {
+            ResizeRecorder::create(path).map_err(|e| {
+                error!(
+                    "Failed to create resize recording {}: {}",
+                    path.display(),
+                    e
+                );
+            })
+        })
+        .transpose()
+    else {
+        return false;
+    };
+
+    let Ok(resize_replay) = config
+        .replay
+        .as_deref()
+        .map(|path| {
+            ResizeReplay::load(path).map_err(|e| {
+                error!("Failed to load resize recording {}: {}", path.display(), e);
+            })
+        })
+        .transpose()
+    else {
+        return false;
+    };
+
+    let event_loop = match EventLoop::with_user_event().build() {
+        Ok(event_loop) => event_loop,
+        Err(e) => {
+            error!("Failed to create the event loop: {}", e);
+            return false;
+        }
+    };
+    event_loop.set_control_flow(ControlFlow::Poll);
+
+    let mut app = App {
+        limiter: config.max_fps.map(FrameLimiter::new),
+        software_vsync: config.software_vsync.map(SoftwareVsync::new),
+        resize_recorder,
+        memory: config.report_mem.map(MemoryReporter::new),
+        resize_replay,
+        started: None,
+        deadline: None,
+        config,
+        on_frame,
+        csv,
+        json,
+        udp,
+        instance: None,
+        gpu: None,
+        states: Vec::new(),
+        render_thread: None,
+        proxy: event_loop.create_proxy(),
+        failed: false,
+    };
+    if let Err(e) = event_loop.run_app(&mut app) {
+        error!("Event loop exited with an error: {}", e);
+        return false;
+    }
+    !app.failed
+}
@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use wgpu::{Adapter, Instance};
+
+/// Periodically logs how many resources of each kind wgpu is holding on to.
+/// A count that keeps growing points at resources that are never dropped,
+/// e.g. render targets recreated on every resize.
+pub struct MemoryReporter {
+    interval: Duration,
+    next_report: Instant,
+}
+
+impl MemoryReporter {
+    pub fn new(interval: Duration) -> MemoryReporter {
+        Self {
+            interval,
+            next_report: Instant::now(),
+        }
+    }
+
+    /// Logs a report for `adapter`'s backend if one is due.
+    pub fn tick(&mut self, instance: &Instance, adapter: &Adapter) {
+        let now = Instant::now();
+        if now < self.next_report {
+            return;
+        }
+        self.next_report = now + self.interval;
+
+        let Some(report) = instance.generate_report() else {
+            warn!("This wgpu backend can't report its resource usage");
+            return;
+        };
+        let hub = report.hub_report(adapter.get_info().backend);
+        info!(
+            "Resources held: {} textures, {} texture views, {} buffers, {} bind groups, {} command buffers, {} render pipelines, {} surfaces",
+            hub.textures.num_allocated,
+            hub.texture_views.num_allocated,
+            hub.buffers.num_allocated,
+            hub.bind_groups.num_allocated,
+            hub.command_buffers.num_allocated,
+            hub.render_pipelines.num_allocated,
+            report.surfaces().num_allocated,
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// Timing information for a single rendered frame.
+pub struct FrameRecord {
+    /// Which window the frame was rendered to, in creation order.
+    pub window: usize,
+    pub index: u64,
+    pub timestamp: SystemTime,
+    pub acquire: Duration,
+    pub submit: Duration,
+    pub present: Duration,
+    pub over_timeout: bool,
+}
+
+impl FrameRecord {
+    fn timestamp_us(&self) -> u128 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros()
+    }
+}
+
+/// Writes one CSV row per rendered frame.
+pub struct CsvWriter {
+    out: BufWriter<File>,
+}
+
+impl CsvWriter {
+    pub fn create(path: &Path) -> io::Result<CsvWriter> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "frame,timestamp_us,acquire_us,over_timeout,window")?;
+        Ok(Self { out })
+    }
+
+    pub fn write(&mut self, record: &FrameRecord) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{},{}",
+            record.index,
+            record.timestamp_us(),
+            record.acquire.as_micros(),
+            record.over_timeout,
+            record.window
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The JSON object written per frame. Field names are part of the output
+/// format, so only ever add to them.
+#[derive(Serialize)]
+struct JsonFrame {
+    frame: u64,
+    window: usize,
+    timestamp_us: u128,
+    acquire_us: u128,
+    submit_us: u128,
+    present_us: u128,
+    over_timeout: bool,
+}
+
+/// Writes one JSON object per line per rendered frame.
+pub struct JsonWriter {
+    out: Box<dyn Write>,
+}
+
+impl JsonWriter {
+    /// Writes to the file at `path`, or to stdout when `path` is `-`.
+    pub fn create(path: &Path) -> io::Result<JsonWriter> {
+        let out: Box<dyn Write> = if path == Path::new("-") {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(Self { out })
+    }
+
+    pub fn write(&mut self, record: &FrameRecord) -> io::Result<()> {
+        let frame = JsonFrame {
+            frame: record.index,
+            window: record.window,
+            timestamp_us: record.timestamp_us(),
+            acquire_us: record.acquire.as_micros(),
+            submit_us: record.submit.as_micros(),
+            present_us: record.present.as_micros(),
+            over_timeout: record.over_timeout,
+        };
+        serde_json::to_writer(&mut self.out, &frame)?;
+        writeln!(self.out)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Sends one `frame,window,acquire_us` line per rendered frame as a UDP
+/// datagram, for watching a run live from another process.
+pub struct UdpSender {
+    socket: UdpSocket,
+}
+
+impl UdpSender {
+    pub fn connect(addr: &str) -> io::Result<UdpSender> {
+        let target = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "address resolved to nothing")
+        })?;
+        let local: SocketAddr = if target.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(target)?;
+        // Never let a slow or absent listener hold up rendering.
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    /// Drops the packet instead of waiting when the socket buffer is full or
+    /// nothing is listening.
+    pub fn send(&mut self, record: &FrameRecord) -> io::Result<()> {
+        let line = format!(
+            "{},{},{}\n",
+            record.index,
+            record.window,
+            record.acquire.as_micros()
+        );
+        match self.socket.send(line.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::ConnectionRefused
+                ) =>
+            {
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
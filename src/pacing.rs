@@ -0,0 +1,103 @@
+use std::time::{Duration, Instant};
+
+/// Caps the frame rate by spacing frame starts at least `interval` apart.
+pub struct FrameLimiter {
+    interval: Duration,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new(max_fps: u32) -> FrameLimiter {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / max_fps as f64),
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Schedules the next frame relative to the start of this one, so a
+    /// stalled frame doesn't push every later frame back.
+    pub fn frame_started(&mut self, start: Instant) {
+        self.next_frame = start + self.interval;
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_frame
+    }
+
+    pub fn next_frame(&self) -> Instant {
+        self.next_frame
+    }
+}
+
+/// Paces frames on a CPU timer at a fixed rate. Ticks are counted from a
+/// fixed epoch rather than from the last frame, so late frames don't make the
+/// schedule drift.
+pub struct SoftwareVsync {
+    epoch: Instant,
+    interval: Duration,
+    next_tick: u32,
+}
+
+impl SoftwareVsync {
+    pub fn new(hz: u32) -> SoftwareVsync {
+        Self {
+            epoch: Instant::now(),
+            interval: Duration::from_secs_f64(1.0 / hz as f64),
+            next_tick: 1,
+        }
+    }
+
+    pub fn next_tick(&self) -> Instant {
+        self.epoch + self.interval * self.next_tick
+    }
+
+    /// Whether the next tick has passed. If so, moves on to the first tick
+    /// after `now`, skipping any that were missed.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        if now < self.next_tick() {
+            return false;
+        }
+        let elapsed_ticks = ((now - self.epoch).as_nanos() / self.interval.as_nanos()) as u32;
+        self.next_tick = elapsed_ticks + 1;
+        true
+    }
+}
+
+/// Keeps the current thread busy for `duration`, standing in for a game's
+/// update step. Unlike sleeping this doesn't give the core up.
+pub fn spin_for(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        std::hint::spin_loop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn software_vsync_ticks_on_a_fixed_grid() {
+        let mut vsync = SoftwareVsync::new(100);
+        let epoch = vsync.epoch;
+        let ms = Duration::from_millis;
+
+        assert!(!vsync.tick(epoch));
+        assert!(!vsync.tick(epoch + ms(9)));
+        assert!(vsync.tick(epoch + ms(10)));
+        assert_eq!(vsync.next_tick(), epoch + ms(20));
+        // A late tick doesn't move the grid.
+        assert!(vsync.tick(epoch + ms(23)));
+        assert_eq!(vsync.next_tick(), epoch + ms(30));
+        assert!(!vsync.tick(epoch + ms(29)));
+    }
+
+    #[test]
+    fn software_vsync_skips_missed_ticks() {
+        let mut vsync = SoftwareVsync::new(100);
+        let epoch = vsync.epoch;
+
+        assert!(vsync.tick(epoch + Duration::from_millis(55)));
+        assert_eq!(vsync.next_tick(), epoch + Duration::from_millis(60));
+    }
+}
@@ -0,0 +1,270 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::{error, warn};
+use wgpu::{Instance, SurfaceError};
+use winit::dpi::PhysicalSize;
+use winit::event::{ElementState, KeyEvent, WindowEvent};
+use winit::event_loop::EventLoopProxy;
+use winit::keyboard::{KeyCode, PhysicalKey};
+use winit::window::WindowId;
+
+use crate::config::Config;
+use crate::gpu::Gpu;
+use crate::memory::MemoryReporter;
+use crate::metrics::FrameRecord;
+use crate::pacing;
+use crate::state::{self, State};
+
+/// How often the event loop wakes up to check for Ctrl-C and `--run-for`
+/// while the render thread does the rendering.
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the render thread sleeps when no window could be rendered to,
+/// e.g. because they're all minimized.
+const IDLE_SLEEP: Duration = Duration::from_millis(10);
+
+/// Sent from the render thread to the event loop.
+pub enum RenderEvent {
+    Frame(FrameRecord),
+    /// A surface error the render thread didn't recover from by itself.
+    SurfaceError(SurfaceError),
+    /// Recreating GPU resources failed, so the render thread stopped rendering.
+    Failed(String),
+    /// Every window has rendered all it was asked to.
+    Finished,
+}
+
+enum Command {
+    /// A window event for the window with this index, named for stall logs.
+    Window(usize, &'static str, Option<WindowCommand>),
+    Stop,
+}
+
+/// Window events forwarded from the event loop.
+enum WindowCommand {
+    Resize(PhysicalSize<u32>),
+    /// Reads the window's size again, e.g. after its scale factor changed.
+    RefreshSize,
+    Occluded(bool),
+    Key(KeyCode),
+    Close,
+}
+
+/// Renders every window from a dedicated thread, the way many engines do,
+/// while the event loop only forwards window events to it.
+pub struct RenderThread {
+    commands: Sender<Command>,
+    /// The index of every window that's still open.
+    windows: Vec<(WindowId, usize)>,
+    handle: JoinHandle<Vec<State>>,
+}
+
+impl RenderThread {
+    /// Moves `states` onto a new thread and starts rendering to them.
+    /// `instance` is needed there to recreate lost surfaces and devices.
+    pub fn spawn(
+        states: Vec<State>,
+        gpu: Arc<Gpu>,
+        instance: Instance,
+        options: &Config,
+        proxy: EventLoopProxy<RenderEvent>,
+    ) -> RenderThread {
+        let windows = states
+            .iter()
+            .map(|state| (state.window().id(), state.index()))
+            .collect();
+        let (commands, receiver) = mpsc::channel();
+        let renderer = Renderer {
+            states,
+            gpu,
+            instance,
+            memory: options.report_mem.map(MemoryReporter::new),
+            options: options.clone(),
+            proxy,
+            commands: receiver,
+        };
+        let handle = thread::Builder::new()
+            .name("render".into())
+            .spawn(move || renderer.run())
+            .expect("failed to spawn the render thread");
+
+        Self {
+            commands,
+            windows,
+            handle,
+        }
+    }
+
+    /// Forwards `event` to the window it's for. Returns whether every window
+    /// has been closed.
+    pub fn window_event(&mut self, window_id: WindowId, event: WindowEvent) -> bool {
+        let Some(position) = self.windows.iter().position(|&(id, _)| id == window_id) else {
+            return false;
+        };
+        let index = self.windows[position].1;
+
+        let name = state::event_name(&event);
+        let command = match event {
+            // Frames aren't requested from the event loop, so these don't matter.
+            WindowEvent::RedrawRequested => return false,
+            WindowEvent::CloseRequested => {
+                self.windows.remove(position);
+                Some(WindowCommand::Close)
+            }
+            WindowEvent::Resized(size) => Some(WindowCommand::Resize(size)),
+            WindowEvent::ScaleFactorChanged { .. } => Some(WindowCommand::RefreshSize),
+            WindowEvent::Occluded(occluded) => Some(WindowCommand::Occluded(occluded)),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => Some(WindowCommand::Key(key)),
+            _ => None,
+        };
+        // Sending only fails once the thread is gone, which `stop` reports.
+        let _ = self.commands.send(Command::Window(index, name, command));
+        self.windows.is_empty()
+    }
+
+    /// Stops rendering and hands back the windows that are still open, for
+    /// their summaries.
+    pub fn stop(self) -> Vec<State> {
+        let _ = self.commands.send(Command::Stop);
+        self.handle.join().unwrap_or_else(|_| {
+            error!("Render thread panicked");
+            Vec::new()
+        })
+    }
+}
+
+/// The render thread's side.
+struct Renderer {
+    states: Vec<State>,
+    gpu: Arc<Gpu>,
+    instance: Instance,
+    memory: Option<MemoryReporter>,
+    options: Config,
+    proxy: EventLoopProxy<RenderEvent>,
+    commands: Receiver<Command>,
+}
+
+impl Renderer {
+    fn run(mut self) -> Vec<State> {
+        let mut finished = false;
+        loop {
+            let commands: Vec<_> = if finished {
+                // Nothing is left to render, so wait for the event loop.
+                match self.commands.recv() {
+                    Ok(command) => vec![command],
+                    Err(_) => return self.states,
+                }
+            } else {
+                self.commands.try_iter().collect()
+            };
+            for command in commands {
+                if !self.handle(command) {
+                    return self.states;
+                }
+            }
+
+            if !finished && !self.render_frames() {
+                finished = true;
+                let _ = self.proxy.send_event(RenderEvent::Finished);
+            }
+        }
+    }
+
+    /// Applies a forwarded window event. Returns false when the thread should stop.
+    fn handle(&mut self, command: Command) -> bool {
+        let Command::Window(index, name, command) = command else {
+            return false;
+        };
+        let Some(position) = self.states.iter().position(|state| state.index() == index) else {
+            return true;
+        };
+
+        let state = &mut self.states[position];
+        state.note_event(name);
+        match command {
+            Some(WindowCommand::Resize(size)) => state.request_resize(size),
+            Some(WindowCommand::RefreshSize) => state.request_resize(state.window().inner_size()),
+            Some(WindowCommand::Occluded(occluded)) => state.set_occluded(occluded),
+            Some(WindowCommand::Key(key)) => state.handle_key(key),
+            Some(WindowCommand::Close) => self.states.remove(position).print_summary(),
+            None => {}
+        }
+        true
+    }
+
+    /// Renders a frame to every window that still has frames to render.
+    /// Returns false once none do, or after sending `RenderEvent::Failed`.
+    fn render_frames(&mut self) -> bool {
+        if self.gpu.is_lost() {
+            match state::recover_lost_device(&mut self.states, &self.instance, &self.options) {
+                Ok(gpu) => self.gpu = gpu,
+                Err(e) => {
+                    let _ = self.proxy.send_event(RenderEvent::Failed(e));
+                    return false;
+                }
+            }
+        }
+        if let Some(memory) = &mut self.memory {
+            memory.tick(&self.instance, &self.gpu.adapter);
+        }
+
+        let mut pending = false;
+        let mut rendered = false;
+        for state in &mut self.states {
+            if state.is_done(self.options.frames) {
+                continue;
+            }
+            pending = true;
+
+            if let Err(e) = state.apply_pending_resize() {
+                let _ = self.proxy.send_event(RenderEvent::Failed(e));
+                return false;
+            }
+            if state.is_minimized() {
+                continue;
+            }
+            pacing::spin_for(self.options.cpu_busy());
+            match state.render() {
+                Ok(None) => {}
+                Ok(Some(record)) => {
+                    rendered = true;
+                    let _ = self.proxy.send_event(RenderEvent::Frame(record));
+                }
+                Err(e) if self.options.once => {
+                    let _ = self.proxy.send_event(RenderEvent::SurfaceError(e));
+                }
+                Err(SurfaceError::Lost) => {
+                    if let Err(e) = state.handle_lost(Some(&self.instance)) {
+                        let _ = self.proxy.send_event(RenderEvent::Failed(e));
+                        return false;
+                    }
+                }
+                Err(SurfaceError::Timeout) => state.record_timeout(),
+                Err(SurfaceError::Outdated) => {
+                    warn!("Surface outdated, recovering");
+                    state.reconfigure();
+                }
+                Err(e) => {
+                    let _ = self.proxy.send_event(RenderEvent::SurfaceError(e));
+                }
+            }
+        }
+
+        if pending && !rendered {
+            thread::sleep(IDLE_SLEEP);
+        }
+        pending
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use winit::dpi::PhysicalSize;
+
+const HEADER: &str = "elapsed_ms,window,width,height";
+
+/// A resize of one window, timed from when the windows were created.
+pub struct ResizeEvent {
+    pub elapsed: Duration,
+    pub window: usize,
+    pub size: PhysicalSize<u32>,
+}
+
+/// Writes every resize event to a CSV file that `--replay` can read back.
+pub struct ResizeRecorder {
+    out: BufWriter<File>,
+}
+
+impl ResizeRecorder {
+    pub fn create(path: &Path) -> io::Result<ResizeRecorder> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "{}", HEADER)?;
+        Ok(Self { out })
+    }
+
+    pub fn record(&mut self, event: &ResizeEvent) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{}",
+            event.elapsed.as_millis(),
+            event.window,
+            event.size.width,
+            event.size.height
+        )
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Resize events read from a recording, handed out as their time comes.
+pub struct ResizeReplay {
+    events: VecDeque<ResizeEvent>,
+}
+
+impl ResizeReplay {
+    pub fn load(path: &Path) -> io::Result<ResizeReplay> {
+        let contents = fs::read_to_string(path)?;
+        let events = contents
+            .lines()
+            .enumerate()
+            .filter(|&(_, line)| !line.is_empty() && line != HEADER)
+            .map(|(number, line)| {
+                parse_event(line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("line {}: expected {}, got {:?}", number + 1, HEADER, line),
+                    )
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { events })
+    }
+
+    /// Takes the next event if it's due `elapsed` after the windows were created.
+    pub fn next_due(&mut self, elapsed: Duration) -> Option<ResizeEvent> {
+        if self.events.front()?.elapsed > elapsed {
+            return None;
+        }
+        self.events.pop_front()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+fn parse_event(line: &str) -> Option<ResizeEvent> {
+    let mut fields = line.split(',').map(str::trim);
+    let event = ResizeEvent {
+        elapsed: Duration::from_millis(fields.next()?.parse().ok()?),
+        window: fields.next()?.parse().ok()?,
+        size: PhysicalSize::new(fields.next()?.parse().ok()?, fields.next()?.parse().ok()?),
+    };
+    fields.next().is_none().then_some(event)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_events() {
+        let event = parse_event("1500,1,800,600").unwrap();
+        assert_eq!(event.elapsed, Duration::from_millis(1500));
+        assert_eq!(event.window, 1);
+        assert_eq!(event.size, PhysicalSize::new(800, 600));
+
+        let event = parse_event(" 0 , 0 , 1 , 2 ").unwrap();
+        assert_eq!(event.size, PhysicalSize::new(1, 2));
+    }
+
+    #[test]
+    fn rejects_malformed_events() {
+        for line in [
+            "",
+            HEADER,
+            "1500,0,800",
+            "1500,0,800,600,1",
+            "1.5,0,800,600",
+            "1500,-1,800,600",
+            "1500;0;800;600",
+        ] {
+            assert!(parse_event(line).is_none(), "{:?} parsed", line);
+        }
+    }
+}
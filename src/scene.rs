@@ -0,0 +1,165 @@
+use std::time::Instant;
+
+use wgpu::{
+    Color, CommandEncoder, CommandEncoderDescriptor, Device, Queue,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, TextureView,
+};
+
+use crate::color;
+use crate::config::{AspectRatio, Config};
+use crate::targets::RenderTargets;
+use crate::timing::GpuTimer;
+use crate::triangle::Triangle;
+
+/// How each frame is drawn, as set on the command line.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneOptions {
+    /// Render passes recorded per frame.
+    pub passes: u32,
+    /// Set by `--aspect` to draw into a letterboxed region of that ratio.
+    pub aspect: Option<AspectRatio>,
+    /// Set by `--animate-color` to clear every frame to its own color.
+    pub color_seed: Option<u64>,
+    /// Whether to put the frame number in the encoder and pass labels.
+    pub label_frames: bool,
+}
+
+impl SceneOptions {
+    pub fn new(options: &Config) -> SceneOptions {
+        Self {
+            passes: options.passes,
+            aspect: options.aspect,
+            color_seed: options.animate_color.then_some(options.seed),
+            label_frames: options.label_frames,
+        }
+    }
+}
+
+/// What gets drawn each frame, independent of where it's drawn to.
+pub struct Scene {
+    triangle: Triangle,
+    clear_color: Color,
+    shader: String,
+    options: SceneOptions,
+    start_time: Instant,
+}
+
+impl Scene {
+    /// The pipeline is built for `targets`, which `record` must later be given.
+    /// `shader` is the WGSL source to draw the triangle with.
+    pub fn new(
+        device: &Device,
+        targets: &RenderTargets,
+        clear_color: Color,
+        shader: &str,
+        options: SceneOptions,
+    ) -> Result<Scene, String> {
+        Ok(Self {
+            triangle: Triangle::new(
+                device,
+                targets.format(),
+                targets.depth_format(),
+                targets.sample_count(),
+                shader,
+            )?,
+            clear_color,
+            shader: shader.to_owned(),
+            options,
+            start_time: Instant::now(),
+        })
+    }
+
+    pub fn clear_color(&self) -> Color {
+        self.clear_color
+    }
+
+    pub fn shader(&self) -> &str {
+        &self.shader
+    }
+
+    pub fn options(&self) -> SceneOptions {
+        self.options
+    }
+
+    /// Creates the encoder for frame number `frame`.
+    pub fn create_encoder(&self, device: &Device, frame: u64) -> CommandEncoder {
+        let label = self
+            .options
+            .label_frames
+            .then(|| format!("Render Encoder #{}", frame));
+        device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some(label.as_deref().unwrap_or("Render Encoder")),
+        })
+    }
+
+    /// Records render passes for frame number `frame` into `encoder`, targeting
+    /// `view`. When `timer` is given the passes are timed on the GPU together.
+    pub fn record(
+        &self,
+        frame: u64,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        targets: &RenderTargets,
+        timer: Option<&GpuTimer>,
+    ) {
+        self.triangle
+            .update(queue, self.start_time.elapsed().as_secs_f32());
+        let clear_color = self
+            .options
+            .color_seed
+            .map_or(self.clear_color, |seed| color::frame_color(seed, frame));
+
+        let label = self
+            .options
+            .label_frames
+            .then(|| format!("Render Pass #{}", frame));
+        let passes = self.options.passes;
+        for pass in 0..passes {
+            let first = pass == 0;
+            let last = pass + 1 == passes;
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some(label.as_deref().unwrap_or("Render Pass")),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: targets.msaa().unwrap_or(view),
+                    resolve_target: targets.msaa().map(|_| view),
+                    ops: wgpu::Operations {
+                        load: if first {
+                            wgpu::LoadOp::Clear(clear_color)
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: targets.depth().map(|view| {
+                    RenderPassDepthStencilAttachment {
+                        view,
+                        depth_ops: Some(wgpu::Operations {
+                            load: if first {
+                                wgpu::LoadOp::Clear(1.0)
+                            } else {
+                                wgpu::LoadOp::Load
+                            },
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: timer.map(|timer| timer.timestamp_writes(first, last)),
+            });
+            if let Some(aspect) = self.options.aspect {
+                let (width, height) = targets.size();
+                let (x, y, width, height) = aspect.letterbox(width, height);
+                render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+            }
+            self.triangle.draw(&mut render_pass);
+            drop(render_pass);
+        }
+
+        if let Some(timer) = timer {
+            timer.resolve(encoder);
+        }
+    }
+}
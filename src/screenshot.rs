@@ -0,0 +1,118 @@
+use std::error::Error;
+use std::path::Path;
+use std::sync::mpsc;
+
+use log::error;
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Extent3d, ImageCopyBuffer,
+    ImageDataLayout, Maintain, MapMode, Texture, TextureFormat, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+use crate::gpu::ErrorScope;
+
+/// A frame copied into a mappable buffer, waiting for the GPU before it can be saved.
+pub struct PendingScreenshot {
+    buffer: Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    bgra: bool,
+}
+
+impl PendingScreenshot {
+    /// Records a copy of `texture` into `encoder`. Returns `None` for formats
+    /// other than 8-bit RGBA/BGRA, which can't be written straight to a PNG,
+    /// or if the readback buffer couldn't be created.
+    pub fn record(
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        texture: &Texture,
+    ) -> Option<PendingScreenshot> {
+        let bgra = match texture.format() {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            _ => return None,
+        };
+
+        let width = texture.width();
+        let height = texture.height();
+        // Rows in the buffer must be padded to a multiple of COPY_BYTES_PER_ROW_ALIGNMENT.
+        let unpadded_bytes_per_row = width * 4;
+        let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let scope = ErrorScope::push(device, "the screenshot buffer");
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        if let Err(e) = scope.finish() {
+            error!("{}, skipping the screenshot", e);
+            return None;
+        }
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        Some(Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            bgra,
+        })
+    }
+
+    /// Waits for the copy to finish and writes the frame to `path` as a PNG.
+    /// Must be called after the encoder passed to `record` was submitted.
+    pub fn save(self, device: &Device, path: &Path) -> Result<(), Box<dyn Error>> {
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(Maintain::Wait);
+        receiver.recv()??;
+
+        let row_bytes = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+
+        if self.bgra {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        image::save_buffer(
+            path,
+            &pixels,
+            self.width,
+            self.height,
+            image::ExtendedColorType::Rgba8,
+        )?;
+        Ok(())
+    }
+}
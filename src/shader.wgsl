@@ -0,0 +1,36 @@
+struct Uniforms {
+    angle: f32,
+};
+
+@group(0) @binding(0)
+var<uniform> uniforms: Uniforms;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) color: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let c = cos(uniforms.angle);
+    let s = sin(uniforms.angle);
+    let rotated = vec2<f32>(
+        in.position.x * c - in.position.y * s,
+        in.position.x * s + in.position.y * c,
+    );
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(rotated, 0.0, 1.0);
+    out.color = in.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
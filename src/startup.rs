@@ -0,0 +1,12 @@
+use std::time::Instant;
+
+use log::info;
+
+use crate::config::Config;
+
+/// Logs how long a phase of startup took since `start`, if `--profile-startup` is set.
+pub fn phase_done(options: &Config, phase: &str, start: Instant) {
+    if options.profile_startup {
+        info!("Startup: {} took {:?}", phase, start.elapsed());
+    }
+}
@@ -0,0 +1,1147 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use log::{debug, error, info, trace, warn};
+use tracing::info_span;
+use wgpu::{
+    Backend, CompositeAlphaMode, Instance, Maintain, PresentMode, Surface, SurfaceConfiguration,
+    SurfaceError, TextureFormat, TextureViewDescriptor,
+};
+use winit::dpi::PhysicalSize;
+use winit::event::WindowEvent;
+use winit::keyboard::KeyCode;
+use winit::window::Window;
+
+use crate::config::{Config, EffectiveConfig};
+use crate::crash;
+use crate::gpu::Gpu;
+use crate::metrics::FrameRecord;
+use crate::scene::{Scene, SceneOptions};
+use crate::screenshot::PendingScreenshot;
+use crate::stats::{FpsCounter, LatencyStats, RollingStats, StallStreak, Thresholds};
+use crate::sweep::Sweep;
+use crate::targets::{self, RenderTargets};
+use crate::timing::GpuTimer;
+use crate::upscale::Upscaler;
+use crate::watchdog::Watchdog;
+
+/// Consecutive `SurfaceError::Lost` errors after which the surface is recreated.
+const LOST_BEFORE_RECREATE: u32 = 3;
+
+/// Everything needed to render into a single window.
+pub struct State {
+    gpu: Arc<Gpu>,
+    window: Arc<Window>,
+    /// Position of this window in creation order, used to tell windows apart in metrics.
+    index: usize,
+    /// Prepended to log lines when there's more than one window.
+    log_prefix: String,
+    surface: Surface<'static>,
+    config: SurfaceConfiguration,
+    size: PhysicalSize<u32>,
+    /// Sizes from resize events that haven't been applied to the surface yet.
+    pending_resize: PendingResize,
+    /// `SurfaceError::Lost` errors since the last frame that rendered.
+    consecutive_lost: u32,
+    /// Set once the surface has been configured with a non-zero size. Acquiring
+    /// from a surface that never was can hang.
+    configured: bool,
+    /// The device's `max_texture_dimension_2d`, which the surface size is
+    /// clamped to. That's the adapter's own limit with `--max-limits`.
+    max_texture_dimension: u32,
+    /// Set while the window has a zero size, during which nothing is rendered.
+    is_minimized: bool,
+    /// Set while the window is fully hidden, during which presenting can block,
+    /// so nothing is rendered.
+    is_occluded: bool,
+    thresholds: Thresholds,
+    /// Consecutive frames whose acquire went over the hard threshold.
+    stall_streak: StallStreak,
+    /// The last window event other than a redraw, named when an acquire is slow.
+    last_event: &'static str,
+    /// Whether to poll the device with `Maintain::Wait` after each submit.
+    poll_wait: bool,
+    /// How long to sleep between submitting and presenting.
+    present_delay: Duration,
+    /// Set by `--no-render`.
+    no_render: bool,
+    /// The `--double-buffer` or `--triple-buffer` preset, named in the summary.
+    buffering_preset: Option<&'static str>,
+    /// Set by `--starve-after`, the frame from which nothing is presented.
+    starve_after: Option<u64>,
+    stats: LatencyStats,
+    /// Present with `--reconfigure-each-frame`, how long each configure took.
+    reconfigure_stats: Option<LatencyStats>,
+    rolling: RollingStats,
+    frame: u64,
+    fps: FpsCounter,
+    scene: Scene,
+    targets: RenderTargets,
+    /// Set by `--internal-res`. The targets then stay at that size and are
+    /// scaled up to the surface instead of following the window.
+    upscaler: Option<Upscaler>,
+    /// Present when `--gpu-timing` is set and the device supports timestamp queries.
+    gpu_timer: Option<GpuTimer>,
+    screenshot_requested: bool,
+    present_modes: Vec<PresentMode>,
+    watchdog: Option<Watchdog>,
+    /// When the previous frame was presented.
+    last_present: Option<Instant>,
+    /// Refresh intervals skipped between presents under vsync.
+    missed_vsyncs: u64,
+    /// Frames skipped because acquiring the surface texture timed out.
+    timeouts: u64,
+    /// The monitor's refresh interval, if winit could tell.
+    refresh_interval: Option<Duration>,
+    pacing_tolerance: f64,
+    /// Set by `--sweep-present-modes`.
+    sweep: Option<Sweep<PresentMode>>,
+    /// Set by `--max-frame-latency-sweep`.
+    latency_sweep: Option<Sweep<u32>>,
+}
+
+impl State {
+    /// `surface` must have been created from `window` on `gpu`'s instance.
+    /// `labelled` prefixes this window's log lines with its index. Fails if the
+    /// adapter can't present to the window or a GPU resource can't be created.
+    pub fn new(
+        gpu: Arc<Gpu>,
+        window: Arc<Window>,
+        surface: Surface<'static>,
+        index: usize,
+        labelled: bool,
+        options: &Config,
+    ) -> Result<State, String> {
+        let size = window.inner_size();
+        let refresh_interval = refresh_interval(&window);
+        let log_prefix = if labelled {
+            format!("[window {}] ", index)
+        } else {
+            String::new()
+        };
+        match refresh_interval {
+            Some(interval) => info!(
+                "{}Monitor refreshes at {:.2} Hz, expecting a vsynced frame every {:?}",
+                log_prefix,
+                1.0 / interval.as_secs_f64(),
+                interval
+            ),
+            None => info!("{}Monitor refresh rate is unknown", log_prefix),
+        }
+
+        let surface_caps = surface.get_capabilities(&gpu.adapter);
+        // Broken drivers, or an adapter that can't present to this window,
+        // report no formats or alpha modes at all.
+        if surface_caps.formats.is_empty() || surface_caps.alpha_modes.is_empty() {
+            return Err(format!(
+                "{}Adapter {} reports no usable surface formats or alpha modes, so it can't present to this window. Surface capabilities: {:#?}",
+                log_prefix,
+                gpu.adapter.get_info().name,
+                surface_caps
+            ));
+        }
+        let surface_format = select_format(
+            options.format.map(Into::into),
+            !options.no_srgb,
+            &surface_caps.formats,
+        );
+        info!(
+            "{}Using {} surface format {:?}",
+            log_prefix,
+            if surface_format.is_srgb() {
+                "sRGB"
+            } else {
+                "linear"
+            },
+            surface_format
+        );
+        let sweep = options.sweep_present_modes.and_then(|interval| {
+            Sweep::new(
+                "Present mode sweep:",
+                "mode",
+                &surface_caps.present_modes,
+                interval,
+            )
+        });
+        if let Some(interval) = options.sweep_present_modes {
+            info!(
+                "{}Sweeping present modes {:?}, {:?} each",
+                log_prefix, surface_caps.present_modes, interval
+            );
+        }
+        let present_mode = if let Some(mode) = sweep.as_ref().and_then(Sweep::current) {
+            mode
+        } else if options.uncapped
+            && !surface_caps.present_modes.contains(&PresentMode::Immediate)
+            && surface_caps.present_modes.contains(&PresentMode::Mailbox)
+        {
+            info!(
+                "{}Immediate present mode is not supported, --uncapped is using Mailbox instead",
+                log_prefix
+            );
+            PresentMode::Mailbox
+        } else {
+            select_present_mode(options.present_mode.into(), &surface_caps.present_modes)
+        };
+        let latency_sweep = options.max_frame_latency_sweep.and_then(|max| {
+            info!(
+                "{}Sweeping frame latencies 1 to {}, {:?} each",
+                log_prefix, max, options.latency_sweep_interval
+            );
+            Sweep::new(
+                "Frame latency sweep:",
+                "latency",
+                &(1..=max).collect::<Vec<_>>(),
+                options.latency_sweep_interval,
+            )
+        });
+        let frame_latency = latency_sweep
+            .as_ref()
+            .and_then(Sweep::current)
+            .unwrap_or(options.frame_latency);
+        info!(
+            "{}Using present mode {:?} with a maximum frame latency of {}{}",
+            log_prefix,
+            present_mode,
+            frame_latency,
+            options
+                .buffering_preset()
+                .map_or(String::new(), |preset| format!(
+                    " from the {} preset",
+                    preset
+                ))
+        );
+        info!(
+            "{}wgpu doesn't report the swapchain size, on {:?} that latency means {}",
+            log_prefix,
+            gpu.adapter.get_info().backend,
+            swapchain_images(gpu.adapter.get_info().backend, frame_latency)
+        );
+        let alpha_mode = select_alpha_mode(
+            options.alpha_mode.map(Into::into),
+            &surface_caps.alpha_modes,
+        );
+        info!("{}Using alpha mode {:?}", log_prefix, alpha_mode);
+        let mut usage = wgpu::TextureUsages::RENDER_ATTACHMENT;
+        // Screenshots copy straight out of the surface texture.
+        if surface_caps.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+            usage |= wgpu::TextureUsages::COPY_SRC;
+        } else {
+            warn!("Surface textures can't be copied from, screenshots are unavailable");
+        }
+        let offscreen = options.offscreen_then_blit
+            && if surface_caps.usages.contains(wgpu::TextureUsages::COPY_DST) {
+                info!(
+                    "{}Rendering offscreen and copying to the surface",
+                    log_prefix
+                );
+                usage |= wgpu::TextureUsages::COPY_DST;
+                true
+            } else {
+                warn!("Surface textures can't be copied to, rendering to the surface directly");
+                false
+            };
+        let max_texture_dimension = gpu.device.limits().max_texture_dimension_2d;
+        let clamped = clamp_size(size, max_texture_dimension, &log_prefix);
+        let config = SurfaceConfiguration {
+            usage,
+            format: surface_format,
+            width: clamped.width,
+            height: clamped.height,
+            present_mode,
+            desired_maximum_frame_latency: frame_latency,
+            alpha_mode,
+            view_formats: vec![],
+        };
+        // Configuring with a zero size fails, e.g. on Wayland before the window is mapped.
+        let configured = config.width > 0 && config.height > 0;
+        if configured {
+            surface.configure(&gpu.device, &config);
+            crash::set_format(config.format);
+            crash::set_present_mode(config.present_mode);
+        } else {
+            info!(
+                "{}Window has no size yet, waiting for a resize to configure the surface",
+                log_prefix
+            );
+        }
+
+        let sample_count = targets::supported_sample_count(
+            &gpu.adapter,
+            config.format,
+            options.depth,
+            options.msaa,
+        );
+        // Textures can't be empty, they're recreated at the real size on the first resize.
+        let (width, height) = match options.internal_res {
+            Some(res) => {
+                info!(
+                    "{}Rendering at {}x{} and scaling up to the surface",
+                    log_prefix, res.width, res.height
+                );
+                (res.width, res.height)
+            }
+            None => (config.width.max(1), config.height.max(1)),
+        };
+        let with_prefix = |e| format!("{}{}", log_prefix, e);
+        let targets = RenderTargets::new(
+            &gpu.device,
+            config.format,
+            width,
+            height,
+            options.depth,
+            sample_count,
+            offscreen || options.internal_res.is_some(),
+        )
+        .map_err(with_prefix)?;
+        let upscaler = options
+            .internal_res
+            .map(|_| Upscaler::new(&gpu.device, targets.offscreen().unwrap(), config.format))
+            .transpose()
+            .map_err(with_prefix)?;
+        let scene = Scene::new(
+            &gpu.device,
+            &targets,
+            options.clear_color(config.format),
+            &options.shader_source()?,
+            SceneOptions::new(options),
+        )
+        .map_err(with_prefix)?;
+        let gpu_timer = options
+            .gpu_timing
+            .then(|| GpuTimer::new(&gpu.device, &gpu.queue))
+            .transpose()
+            .map_err(with_prefix)?
+            .flatten();
+        let warmup = options.warmup_for(options.frames);
+
+        Ok(Self {
+            gpu,
+            window,
+            index,
+            stats: LatencyStats::new(format!("{}Get current texture", log_prefix), warmup),
+            reconfigure_stats: options
+                .reconfigure_each_frame
+                .then(|| LatencyStats::new(format!("{}Surface configure", log_prefix), warmup)),
+            log_prefix,
+            surface,
+            config,
+            size,
+            pending_resize: PendingResize::new(size),
+            consecutive_lost: 0,
+            configured,
+            max_texture_dimension,
+            is_minimized: false,
+            is_occluded: false,
+            thresholds: options.thresholds(),
+            stall_streak: StallStreak::default(),
+            last_event: "none",
+            rolling: RollingStats::new(options.window_size as usize),
+            poll_wait: options.poll_wait,
+            present_delay: Duration::from_millis(options.present_delay_ms),
+            no_render: options.no_render,
+            buffering_preset: options.buffering_preset(),
+            starve_after: options.starve_after,
+            frame: 0,
+            fps: FpsCounter::new(),
+            scene,
+            targets,
+            upscaler,
+            gpu_timer,
+            screenshot_requested: false,
+            present_modes: surface_caps.present_modes,
+            watchdog: (options.watchdog_secs > 0).then(|| {
+                Watchdog::spawn(
+                    Duration::from_secs(options.watchdog_secs),
+                    options.watchdog_abort,
+                )
+            }),
+            last_present: None,
+            missed_vsyncs: 0,
+            timeouts: 0,
+            refresh_interval,
+            pacing_tolerance: options.pacing_tolerance / 100.0,
+            sweep,
+            latency_sweep,
+        })
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn surface(&self) -> &Surface<'static> {
+        &self.surface
+    }
+
+    pub fn effective_config(&self) -> EffectiveConfig {
+        let info = self.gpu.adapter.get_info();
+        EffectiveConfig {
+            backend: format!("{:?}", info.backend),
+            adapter: info.name,
+            format: format!("{:?}", self.config.format),
+            present_mode: format!("{:?}", self.config.present_mode),
+            alpha_mode: format!("{:?}", self.config.alpha_mode),
+            frame_latency: self.config.desired_maximum_frame_latency,
+            width: self.config.width,
+            height: self.config.height,
+        }
+    }
+
+    /// Moves this window onto a freshly created device after the old one was
+    /// lost, recreating every resource that belonged to the old device.
+    pub fn replace_gpu(&mut self, gpu: Arc<Gpu>) -> Result<(), String> {
+        self.gpu = gpu;
+        self.max_texture_dimension = self.gpu.device.limits().max_texture_dimension_2d;
+        if !self.is_minimized {
+            let clamped = clamp_size(self.size, self.max_texture_dimension, &self.log_prefix);
+            self.config.width = clamped.width;
+            self.config.height = clamped.height;
+        }
+        self.configure_surface();
+        let (width, height) = match self.upscaler {
+            Some(_) => self.targets.size(),
+            None => (self.config.width, self.config.height),
+        };
+        let with_prefix = |e| format!("{}{}", self.log_prefix, e);
+        self.targets
+            .resize(&self.gpu.device, width, height)
+            .map_err(with_prefix)?;
+        if self.upscaler.is_some() {
+            self.upscaler = Some(
+                Upscaler::new(
+                    &self.gpu.device,
+                    self.targets.offscreen().unwrap(),
+                    self.config.format,
+                )
+                .map_err(with_prefix)?,
+            );
+        }
+        self.scene = Scene::new(
+            &self.gpu.device,
+            &self.targets,
+            self.scene.clear_color(),
+            self.scene.shader(),
+            self.scene.options(),
+        )
+        .map_err(with_prefix)?;
+        if self.gpu_timer.is_some() {
+            self.gpu_timer =
+                GpuTimer::new(&self.gpu.device, &self.gpu.queue).map_err(with_prefix)?;
+        }
+        info!("{}Recreated GPU resources for new device", self.log_prefix);
+        Ok(())
+    }
+
+    /// Switches to the next present mode the surface supports.
+    pub fn cycle_present_mode(&mut self) {
+        let Some(next) = self
+            .present_modes
+            .iter()
+            .position(|&mode| mode == self.config.present_mode)
+            .map_or(self.present_modes.first(), |i| {
+                self.present_modes.get((i + 1) % self.present_modes.len())
+            })
+            .copied()
+        else {
+            warn!("Surface reports no present modes to switch between");
+            return;
+        };
+
+        info!(
+            "{}Switching present mode {:?} -> {:?}",
+            self.log_prefix, self.config.present_mode, next
+        );
+        self.config.present_mode = next;
+        self.configure_surface();
+    }
+
+    /// Saves the next rendered frame to a PNG in the working directory.
+    pub fn request_screenshot(&mut self) {
+        if self.config.usage.contains(wgpu::TextureUsages::COPY_SRC) {
+            self.screenshot_requested = true;
+        } else {
+            warn!("Surface textures can't be copied from, ignoring screenshot request");
+        }
+    }
+
+    fn take_screenshot_request(&mut self) -> bool {
+        std::mem::take(&mut self.screenshot_requested)
+    }
+
+    pub fn print_summary(&self) {
+        if let Some(preset) = self.buffering_preset {
+            info!(
+                "{}Rendered with the {} preset ({:?}, frame latency {})",
+                self.log_prefix,
+                preset,
+                self.config.present_mode,
+                self.config.desired_maximum_frame_latency
+            );
+        }
+        self.stats.print_summary();
+        if let Some(stats) = &self.reconfigure_stats {
+            stats.print_summary();
+        }
+        info!("{}Acquire timeouts: {}", self.log_prefix, self.timeouts);
+        info!(
+            "{}Longest run of slow acquires: {}",
+            self.log_prefix,
+            self.stall_streak.longest()
+        );
+        if self.refresh_interval.is_some() {
+            info!("{}Missed vsyncs: {}", self.log_prefix, self.missed_vsyncs);
+        }
+        if let Some(sweep) = &self.sweep {
+            sweep.print_comparison();
+        }
+        if let Some(sweep) = &self.latency_sweep {
+            sweep.print_comparison();
+        }
+    }
+
+    /// Prints the latency summary so far and starts a new one, for comparing
+    /// before and after something changed within one run.
+    pub fn dump_and_reset_summary(&mut self) {
+        self.stats.print_summary();
+        self.stats.reset();
+        if let Some(stats) = &mut self.reconfigure_stats {
+            stats.print_summary();
+            stats.reset();
+        }
+        info!("{}Latency summary reset", self.log_prefix);
+    }
+
+    /// Counts a `SurfaceError::Timeout`. Nothing was acquired, so the frame
+    /// is skipped and acquiring is retried on the next one.
+    pub fn record_timeout(&mut self) {
+        self.timeouts += 1;
+        warn!(
+            "{}[frame {}] Acquiring the surface texture timed out, skipping the frame ({} timeouts so far, last event before stall: {})",
+            self.log_prefix, self.frame, self.timeouts, self.last_event
+        );
+    }
+
+    /// Remembers the name of the last window event other than a redraw this
+    /// window got, for the next slow acquire to mention.
+    pub fn note_event(&mut self, name: &'static str) {
+        self.last_event = name;
+    }
+
+    /// Whether a present mode or frame latency sweep was requested and has
+    /// gone through every value.
+    pub fn sweep_finished(&self) -> bool {
+        self.sweep.as_ref().is_some_and(Sweep::is_finished)
+            || self.latency_sweep.as_ref().is_some_and(Sweep::is_finished)
+    }
+
+    /// Whether this window has rendered the `frames` it was asked for or
+    /// finished its sweep.
+    pub fn is_done(&self, frames: Option<u64>) -> bool {
+        self.sweep_finished() || frames.is_some_and(|frames| self.frame >= frames)
+    }
+
+    /// Handles the keyboard shortcuts available in every window.
+    pub fn handle_key(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::KeyS => self.request_screenshot(),
+            KeyCode::KeyV => self.cycle_present_mode(),
+            KeyCode::KeyP => self.dump_and_reset_summary(),
+            _ => {}
+        }
+    }
+
+    /// Records a new window size to apply before the next frame. Dragging a
+    /// window edge fires many resize events per frame, and configuring the
+    /// surface for each of them is expensive enough to cause stalls itself.
+    pub fn request_resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.pending_resize.request(new_size);
+    }
+
+    /// Configures the surface for the latest size recorded by `request_resize`, if any.
+    pub fn apply_pending_resize(&mut self) -> Result<(), String> {
+        match self.pending_resize.take() {
+            Some(size) => self.resize(size),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether rendering is paused because the window is minimized.
+    pub fn is_minimized(&self) -> bool {
+        self.is_minimized
+    }
+
+    /// Pauses rendering while the window is occluded and resumes it after.
+    pub fn set_occluded(&mut self, occluded: bool) {
+        if occluded == self.is_occluded {
+            return;
+        }
+        self.is_occluded = occluded;
+        // The pause isn't missed vsyncs, so pacing starts over on resume.
+        self.last_present = None;
+        if occluded {
+            info!(
+                "{}[frame {}] Window occluded, pausing rendering",
+                self.log_prefix, self.frame
+            );
+        } else {
+            info!(
+                "{}[frame {}] Window no longer occluded, resuming rendering",
+                self.log_prefix, self.frame
+            );
+        }
+    }
+
+    /// Fails if the render targets couldn't be recreated at the new size.
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) -> Result<(), String> {
+        // A minimized window has a zero size on some platforms, and acquiring
+        // from a surface left at the old size can block forever.
+        let minimized = new_size.width == 0 || new_size.height == 0;
+        if minimized != self.is_minimized {
+            self.is_minimized = minimized;
+            if minimized {
+                info!("{}Window minimized, pausing rendering", self.log_prefix);
+            } else {
+                info!("{}Window restored, resuming rendering", self.log_prefix);
+            }
+        }
+
+        if !minimized {
+            self.size = new_size;
+            let clamped = clamp_size(new_size, self.max_texture_dimension, &self.log_prefix);
+            self.config.width = clamped.width;
+            self.config.height = clamped.height;
+            self.configure_surface();
+            self.resize_targets(clamped.width, clamped.height)?;
+            info!(
+                "{}[frame {}] Resized {} {}",
+                self.log_prefix, self.frame, self.config.width, self.config.height
+            );
+        }
+        Ok(())
+    }
+
+    /// Resizes the render targets to match the surface, unless they're kept
+    /// at the `--internal-res` size.
+    fn resize_targets(&mut self, width: u32, height: u32) -> Result<(), String> {
+        if self.upscaler.is_some() {
+            return Ok(());
+        }
+        self.targets
+            .resize(&self.gpu.device, width, height)
+            .map_err(|e| format!("{}{}", self.log_prefix, e))
+    }
+
+    fn configure_surface(&mut self) {
+        if self.config.width == 0 || self.config.height == 0 {
+            return;
+        }
+        self.surface.configure(&self.gpu.device, &self.config);
+        crash::set_format(self.config.format);
+        crash::set_present_mode(self.config.present_mode);
+        self.configured = true;
+    }
+
+    /// Recovers from `SurfaceError::Lost`. Reconfiguring is usually enough,
+    /// but some drivers need a new surface, so after a few losses in a row
+    /// the surface is recreated from the window.
+    pub fn handle_lost(&mut self, instance: Option<&Instance>) -> Result<(), String> {
+        self.consecutive_lost += 1;
+        let Some(instance) = instance.filter(|_| self.consecutive_lost >= LOST_BEFORE_RECREATE)
+        else {
+            return self.resize(self.window.inner_size());
+        };
+
+        warn!(
+            "{}[frame {}] Surface lost {} times in a row, recreating it",
+            self.log_prefix, self.frame, self.consecutive_lost
+        );
+        match instance.create_surface(self.window.clone()) {
+            Ok(surface) => {
+                self.surface = surface;
+                self.configure_surface();
+                self.consecutive_lost = 0;
+                Ok(())
+            }
+            Err(e) => {
+                error!(
+                    "{}[frame {}] Failed to recreate the surface ({}), reconfiguring the old one",
+                    self.log_prefix, self.frame, e
+                );
+                self.resize(self.window.inner_size())
+            }
+        }
+    }
+
+    /// Configures the surface again with the last known size, e.g. after it became outdated.
+    pub fn reconfigure(&mut self) {
+        info!(
+            "{}[frame {}] Reconfiguring surface at last known size {} {}",
+            self.log_prefix, self.frame, self.config.width, self.config.height
+        );
+        self.configure_surface();
+    }
+
+    /// Returns `Ok(None)` without rendering until the surface has been configured.
+    #[tracing::instrument(name = "frame", skip_all, fields(window = self.index, frame = self.frame))]
+    pub fn render(&mut self) -> Result<Option<FrameRecord>, SurfaceError> {
+        if !self.configured {
+            trace!(
+                "{}Surface isn't configured yet, skipping frame",
+                self.log_prefix
+            );
+            return Ok(None);
+        }
+        if self.is_occluded {
+            trace!("{}Window is occluded, skipping frame", self.log_prefix);
+            return Ok(None);
+        }
+
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.arm(self.frame);
+        }
+        if self.reconfigure_stats.is_some() {
+            self.reconfigure_timed();
+        }
+        let result = self.render_frame();
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.disarm();
+        }
+        result.map(Some)
+    }
+
+    /// Configures the surface again with the same configuration, for `--reconfigure-each-frame`.
+    fn reconfigure_timed(&mut self) {
+        let start = Instant::now();
+        info_span!("configure").in_scope(|| self.configure_surface());
+        let configure_time = start.elapsed();
+        let over_timeout = self.thresholds.check(
+            format_args!(
+                "{}[frame {}] Surface configure",
+                self.log_prefix, self.frame
+            ),
+            configure_time,
+        );
+        if let Some(stats) = &mut self.reconfigure_stats {
+            stats.record(configure_time, over_timeout);
+        }
+    }
+
+    /// Logs the time since the previous present and, under vsync, counts the
+    /// refresh intervals it skipped. Warns when the time isn't close to a whole
+    /// number of intervals, which points at frame pacing trouble even when no
+    /// single acquire is slow.
+    fn check_present_pacing(&mut self, present_start: Instant) {
+        let Some(last_present) = self.last_present.replace(present_start) else {
+            return;
+        };
+        let delta = present_start - last_present;
+        debug!(
+            "{}[frame {}] Time since last present: {:?}",
+            self.log_prefix, self.frame, delta
+        );
+
+        let vsync = matches!(
+            self.config.present_mode,
+            PresentMode::Fifo | PresentMode::FifoRelaxed | PresentMode::AutoVsync
+        );
+        let Some(expected) = self.refresh_interval.filter(|_| vsync) else {
+            return;
+        };
+        let intervals = delta.as_secs_f64() / expected.as_secs_f64();
+        let whole = intervals.round().max(1.0);
+        if whole > 1.0 {
+            let missed = whole as u64 - 1;
+            self.missed_vsyncs += missed;
+            warn!(
+                "{}[frame {}] Missed {} vsync(s): presents were {:?} apart, expected {:?}",
+                self.log_prefix, self.frame, missed, delta, expected
+            );
+        }
+        if (intervals - whole).abs() > self.pacing_tolerance {
+            warn!(
+                "{}[frame {}] Presents were {:?} apart, not a multiple of the {:?} refresh interval",
+                self.log_prefix, self.frame, delta, expected
+            );
+        }
+    }
+
+    fn render_frame(&mut self) -> Result<FrameRecord, SurfaceError> {
+        let timestamp = SystemTime::now();
+        let timer_start = Instant::now();
+        let output = info_span!("acquire").in_scope(|| self.surface.get_current_texture())?;
+        let acquire_time = timer_start.elapsed();
+        let over_timeout = self.thresholds.check_streak(
+            format_args!(
+                "{}[frame {}] Get current texture (last event before stall: {})",
+                self.log_prefix, self.frame, self.last_event
+            ),
+            acquire_time,
+            &mut self.stall_streak,
+        );
+        self.stats.record(acquire_time, over_timeout);
+        self.rolling.record(acquire_time);
+        let next_mode = self
+            .sweep
+            .as_mut()
+            .and_then(|sweep| sweep.record(Instant::now(), acquire_time));
+        let next_latency = self
+            .latency_sweep
+            .as_mut()
+            .and_then(|sweep| sweep.record(Instant::now(), acquire_time));
+
+        let view = output
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+        let mut encoder = self.scene.create_encoder(&self.gpu.device, self.frame);
+
+        if !self.no_render {
+            self.scene.record(
+                self.frame,
+                &self.gpu.queue,
+                &mut encoder,
+                self.targets.offscreen().unwrap_or(&view),
+                &self.targets,
+                self.gpu_timer.as_ref(),
+            );
+            match &self.upscaler {
+                Some(upscaler) => upscaler.draw(&mut encoder, &view),
+                None => self.targets.copy_offscreen(&mut encoder, &output.texture),
+            }
+        }
+
+        let screenshot = self
+            .take_screenshot_request()
+            .then(|| PendingScreenshot::record(&self.gpu.device, &mut encoder, &output.texture));
+
+        let submit_start = Instant::now();
+        info_span!("submit").in_scope(|| self.gpu.queue.submit(std::iter::once(encoder.finish())));
+        let submit_time = submit_start.elapsed();
+        self.thresholds.check(
+            format_args!("{}[frame {}] Queue submit", self.log_prefix, self.frame),
+            submit_time,
+        );
+
+        if self.poll_wait {
+            let poll_start = Instant::now();
+            info_span!("poll").in_scope(|| self.gpu.device.poll(Maintain::Wait));
+            self.thresholds.check(
+                format_args!("{}[frame {}] Device poll", self.log_prefix, self.frame),
+                poll_start.elapsed(),
+            );
+        }
+
+        if !self.present_delay.is_zero() {
+            info_span!("present delay").in_scope(|| thread::sleep(self.present_delay));
+        }
+
+        let present_time = if self.starve_after.is_some_and(|frames| self.frame >= frames) {
+            if self.starve_after == Some(self.frame) {
+                warn!(
+                    "{}[frame {}] No longer presenting, acquires should start blocking once every swapchain image is taken",
+                    self.log_prefix, self.frame
+                );
+            }
+            // Dropping the texture discards it without returning the image to the swapchain.
+            drop(output);
+            Duration::ZERO
+        } else {
+            trace!("Present");
+            let present_start = Instant::now();
+            info_span!("present").in_scope(|| output.present());
+            let present_time = present_start.elapsed();
+            self.check_present_pacing(present_start);
+            self.thresholds.check(
+                format_args!("{}[frame {}] Present", self.log_prefix, self.frame),
+                present_time,
+            );
+            present_time
+        };
+
+        if let Some(pass_time) = self
+            .gpu_timer
+            .as_ref()
+            .and_then(|timer| timer.read(&self.gpu.device))
+        {
+            info!(
+                "{}[frame {}] GPU render pass took {:?}",
+                self.log_prefix, self.frame, pass_time
+            );
+        }
+
+        match screenshot {
+            Some(Some(screenshot)) => {
+                let path = PathBuf::from(format!("screenshot-{}-{}.png", self.index, self.frame));
+                match screenshot.save(&self.gpu.device, &path) {
+                    Ok(()) => info!("Saved screenshot to {}", path.display()),
+                    Err(e) => error!("Failed to save screenshot: {}", e),
+                }
+            }
+            Some(None) => warn!(
+                "Screenshots of {:?} surfaces aren't supported",
+                self.config.format
+            ),
+            None => {}
+        }
+
+        if let Some(fps) = self.fps.tick() {
+            self.window.set_title(&format!(
+                "wgpu_blocking_repro {}— {:.0} fps",
+                self.log_prefix, fps
+            ));
+            if let Some((mean, std_dev)) = self.rolling.mean_and_std_dev() {
+                info!(
+                    "{}Get current texture over the last {} frames: mean {:?}, std dev {:?}",
+                    self.log_prefix,
+                    self.rolling.len(),
+                    mean,
+                    std_dev
+                );
+            }
+        }
+
+        // The surface can't be reconfigured while a texture from it is still held.
+        if let Some(mode) = next_mode {
+            info!(
+                "{}Sweep switching to present mode {:?}",
+                self.log_prefix, mode
+            );
+            self.config.present_mode = mode;
+            self.configure_surface();
+        }
+        if let Some(latency) = next_latency {
+            info!(
+                "{}Sweep switching to a maximum frame latency of {}",
+                self.log_prefix, latency
+            );
+            self.config.desired_maximum_frame_latency = latency;
+            self.configure_surface();
+        }
+
+        let record = FrameRecord {
+            window: self.index,
+            index: self.frame,
+            timestamp,
+            acquire: acquire_time,
+            submit: submit_time,
+            present: present_time,
+            over_timeout,
+        };
+        self.frame += 1;
+        crash::set_frame(self.frame);
+        self.consecutive_lost = 0;
+
+        Ok(record)
+    }
+}
+
+/// Coalesces resize events so the surface is configured at most once per frame.
+struct PendingResize {
+    /// The size most recently requested, whether applied yet or not. Comparing
+    /// against this rather than the surface size means restoring a minimized
+    /// window to its old size still counts as a change.
+    last_requested: PhysicalSize<u32>,
+    pending: Option<PhysicalSize<u32>>,
+}
+
+impl PendingResize {
+    fn new(size: PhysicalSize<u32>) -> PendingResize {
+        Self {
+            last_requested: size,
+            pending: None,
+        }
+    }
+
+    fn request(&mut self, size: PhysicalSize<u32>) {
+        if size != self.last_requested {
+            self.last_requested = size;
+            self.pending = Some(size);
+        }
+    }
+
+    fn take(&mut self) -> Option<PhysicalSize<u32>> {
+        self.pending.take()
+    }
+}
+
+/// Replaces a lost device with a new one and moves every window in `states` onto it.
+pub fn recover_lost_device(
+    states: &mut [State],
+    instance: &Instance,
+    options: &Config,
+) -> Result<Arc<Gpu>, String> {
+    warn!("Recreating lost device");
+    let gpu = Arc::new(pollster::block_on(Gpu::new(
+        instance,
+        states.first().map(State::surface),
+        options,
+    ))?);
+    for state in states {
+        state.replace_gpu(gpu.clone())?;
+    }
+    Ok(gpu)
+}
+
+/// Clamps `size` so neither side exceeds `max`, since configuring a surface
+/// larger than the device allows fails and can leave it unusable.
+fn clamp_size(size: PhysicalSize<u32>, max: u32, log_prefix: &str) -> PhysicalSize<u32> {
+    let clamped = PhysicalSize::new(size.width.min(max), size.height.min(max));
+    if clamped != size {
+        warn!(
+            "{}Window is {}x{}, larger than the maximum texture size of {}, clamping the surface to {}x{}",
+            log_prefix, size.width, size.height, max, clamped.width, clamped.height
+        );
+    }
+    clamped
+}
+
+/// The name of `event`'s variant, for log messages.
+pub fn event_name(event: &WindowEvent) -> &'static str {
+    match event {
+        WindowEvent::ActivationTokenDone { .. } => "ActivationTokenDone",
+        WindowEvent::Resized(_) => "Resized",
+        WindowEvent::Moved(_) => "Moved",
+        WindowEvent::CloseRequested => "CloseRequested",
+        WindowEvent::Destroyed => "Destroyed",
+        WindowEvent::DroppedFile(_) => "DroppedFile",
+        WindowEvent::HoveredFile(_) => "HoveredFile",
+        WindowEvent::HoveredFileCancelled => "HoveredFileCancelled",
+        WindowEvent::Focused(_) => "Focused",
+        WindowEvent::KeyboardInput { .. } => "KeyboardInput",
+        WindowEvent::ModifiersChanged(_) => "ModifiersChanged",
+        WindowEvent::Ime(_) => "Ime",
+        WindowEvent::CursorMoved { .. } => "CursorMoved",
+        WindowEvent::CursorEntered { .. } => "CursorEntered",
+        WindowEvent::CursorLeft { .. } => "CursorLeft",
+        WindowEvent::MouseWheel { .. } => "MouseWheel",
+        WindowEvent::MouseInput { .. } => "MouseInput",
+        WindowEvent::PinchGesture { .. } => "PinchGesture",
+        WindowEvent::PanGesture { .. } => "PanGesture",
+        WindowEvent::DoubleTapGesture { .. } => "DoubleTapGesture",
+        WindowEvent::RotationGesture { .. } => "RotationGesture",
+        WindowEvent::TouchpadPressure { .. } => "TouchpadPressure",
+        WindowEvent::AxisMotion { .. } => "AxisMotion",
+        WindowEvent::Touch(_) => "Touch",
+        WindowEvent::ScaleFactorChanged { .. } => "ScaleFactorChanged",
+        WindowEvent::ThemeChanged(_) => "ThemeChanged",
+        WindowEvent::Occluded(_) => "Occluded",
+        WindowEvent::RedrawRequested => "RedrawRequested",
+    }
+}
+
+/// How a backend turns the maximum frame latency into swapchain images, as
+/// wgpu-hal does it in this version.
+fn swapchain_images(backend: Backend, frame_latency: u32) -> String {
+    match backend {
+        Backend::Vulkan => format!(
+            "{} swapchain images, or the driver's minimum if that's more",
+            frame_latency + 1
+        ),
+        Backend::Dx12 => format!("{} back buffers", frame_latency + 1),
+        Backend::Metal => format!("{} drawables", (frame_latency + 1).clamp(2, 3)),
+        Backend::Gl => "nothing, the driver decides how many buffers to use".to_string(),
+        _ => "an unknown number of swapchain images".to_string(),
+    }
+}
+
+/// How long one refresh of the monitor `window` is on takes.
+fn refresh_interval(window: &Window) -> Option<Duration> {
+    let millihertz = window.current_monitor()?.refresh_rate_millihertz()?;
+    Some(Duration::from_secs_f64(1000.0 / millihertz as f64))
+}
+
+/// Returns `requested` if the surface supports it, otherwise the first sRGB
+/// format (or non-sRGB one when `srgb` is false), or the first format if none match.
+fn select_format(
+    requested: Option<TextureFormat>,
+    srgb: bool,
+    supported: &[TextureFormat],
+) -> TextureFormat {
+    let fallback = supported
+        .iter()
+        .copied()
+        .find(|f| f.is_srgb() == srgb)
+        .unwrap_or(supported[0]);
+    match requested {
+        None => fallback,
+        Some(format) if supported.contains(&format) => format,
+        Some(format) => {
+            warn!(
+                "Surface format {:?} is not supported (supported: {:?}), falling back to {:?}",
+                format, supported, fallback
+            );
+            fallback
+        }
+    }
+}
+
+/// Returns `requested` if the surface supports it, otherwise the first supported alpha mode.
+fn select_alpha_mode(
+    requested: Option<CompositeAlphaMode>,
+    supported: &[CompositeAlphaMode],
+) -> CompositeAlphaMode {
+    match requested {
+        None => supported[0],
+        Some(mode) if supported.contains(&mode) => mode,
+        Some(mode) => {
+            warn!(
+                "Alpha mode {:?} is not supported (supported: {:?}), falling back to {:?}",
+                mode, supported, supported[0]
+            );
+            supported[0]
+        }
+    }
+}
+
+/// Returns `requested` if the surface supports it, otherwise falls back to `AutoVsync`.
+fn select_present_mode(requested: PresentMode, supported: &[PresentMode]) -> PresentMode {
+    match requested {
+        // The automatic modes are always supported, wgpu resolves them itself.
+        PresentMode::AutoVsync | PresentMode::AutoNoVsync => requested,
+        _ if supported.contains(&requested) => requested,
+        _ => {
+            warn!(
+                "Present mode {:?} is not supported (supported: {:?}), falling back to {:?}",
+                requested,
+                supported,
+                PresentMode::AutoVsync
+            );
+            PresentMode::AutoVsync
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restoring_to_the_old_size_after_minimizing_resizes() {
+        let size = PhysicalSize::new(800, 600);
+        let mut pending = PendingResize::new(size);
+
+        pending.request(PhysicalSize::new(0, 0));
+        assert_eq!(pending.take(), Some(PhysicalSize::new(0, 0)));
+        pending.request(size);
+        assert_eq!(pending.take(), Some(size));
+    }
+
+    #[test]
+    fn repeated_sizes_are_coalesced() {
+        let size = PhysicalSize::new(800, 600);
+        let mut pending = PendingResize::new(size);
+
+        pending.request(size);
+        assert_eq!(pending.take(), None);
+        pending.request(PhysicalSize::new(1024, 768));
+        pending.request(PhysicalSize::new(1280, 720));
+        assert_eq!(pending.take(), Some(PhysicalSize::new(1280, 720)));
+        assert_eq!(pending.take(), None);
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use log::{error, info, warn};
+
+/// Soft and hard limits for how long a timed phase may take before it's logged.
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    /// Exceeding this logs a warning.
+    pub soft: Duration,
+    /// Exceeding this counts as over the timeout and logs an error. Through
+    /// `check_streak` it logs a warning instead, errors once `STREAK_ERROR`
+    /// frames in a row exceed it and reports a sustained stall at
+    /// `STREAK_STALL`. Zero logs every frame without counting any of them as
+    /// over it.
+    pub hard: Duration,
+}
+
+impl Thresholds {
+    /// Logs `duration` at the level its threshold calls for and returns
+    /// whether it exceeded the hard threshold.
+    pub fn check(&self, what: fmt::Arguments, duration: Duration) -> bool {
+        if self.hard.is_zero() {
+            error!("{} took {}ms", what, duration.as_millis());
+            return false;
+        }
+        let over_hard = duration > self.hard;
+        if over_hard {
+            error!("{} took {}ms", what, duration.as_millis());
+        } else if duration > self.soft {
+            warn!("{} took {}ms", what, duration.as_millis());
+        }
+        over_hard
+    }
+
+    /// Like `check`, but frames over the hard threshold are logged through
+    /// `streak`, so a single slow frame doesn't log an error.
+    pub fn check_streak(
+        &self,
+        what: fmt::Arguments,
+        duration: Duration,
+        streak: &mut StallStreak,
+    ) -> bool {
+        if self.hard.is_zero() {
+            return self.check(what, duration);
+        }
+        let over_hard = duration > self.hard;
+        if over_hard {
+            streak.extend(what, duration);
+        } else {
+            streak.end();
+            if duration > self.soft {
+                warn!("{} took {}ms", what, duration.as_millis());
+            }
+        }
+        over_hard
+    }
+}
+
+/// Streak length from which frames over the timeout are logged as errors.
+const STREAK_ERROR: u32 = 5;
+/// Streak length from which frames over the timeout are logged as a sustained stall.
+const STREAK_STALL: u32 = 20;
+
+/// Consecutive frames over the hard threshold, logged louder the longer it lasts.
+#[derive(Default)]
+pub struct StallStreak {
+    frames: u32,
+    longest: u32,
+}
+
+impl StallStreak {
+    fn extend(&mut self, what: fmt::Arguments, duration: Duration) {
+        self.frames += 1;
+        self.longest = self.longest.max(self.frames);
+        let millis = duration.as_millis();
+        match self.frames {
+            // Never quieter than a frame that's only over the soft threshold.
+            ..STREAK_ERROR => warn!("{} took {}ms", what, millis),
+            STREAK_ERROR..STREAK_STALL => error!(
+                "{} took {}ms, {} slow frames in a row",
+                what, millis, self.frames
+            ),
+            _ => error!(
+                "SUSTAINED STALL: {} took {}ms, {} slow frames in a row",
+                what, millis, self.frames
+            ),
+        }
+    }
+
+    fn end(&mut self) {
+        if self.frames >= STREAK_ERROR {
+            info!("Stall over after {} slow frames in a row", self.frames);
+        }
+        self.frames = 0;
+    }
+
+    /// The most consecutive frames that were over the hard threshold.
+    pub fn longest(&self) -> u32 {
+        self.longest
+    }
+}
+
+/// Every duration of the timed phase recorded during the run.
+pub struct LatencyStats {
+    label: String,
+    samples: Vec<Duration>,
+    over_timeout: usize,
+    /// Frames still to be left out before recording starts.
+    warmup: u32,
+    /// Frames that were left out as warmup.
+    warmed_up: u32,
+}
+
+impl LatencyStats {
+    /// The first `warmup` recorded durations are dropped, so one-off startup
+    /// costs don't skew the summary.
+    pub fn new(label: String, warmup: u32) -> LatencyStats {
+        Self {
+            label,
+            samples: Vec::new(),
+            over_timeout: 0,
+            warmup,
+            warmed_up: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration, over_timeout: bool) {
+        if self.warmup > 0 {
+            self.warmup -= 1;
+            self.warmed_up += 1;
+            if self.warmup == 0 {
+                info!(
+                    "{} warmup finished, recording from the next frame",
+                    self.label
+                );
+            }
+            return;
+        }
+        self.samples.push(duration);
+        if over_timeout {
+            self.over_timeout += 1;
+        }
+    }
+
+    /// Forgets every recorded sample, starting a fresh summary.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.over_timeout = 0;
+    }
+
+    pub fn samples(&self) -> &[Duration] {
+        &self.samples
+    }
+
+    pub fn print_summary(&self) {
+        info!("{} summary:", self.label);
+        if self.samples.is_empty() && self.warmed_up > 0 {
+            info!(
+                "  all {} frames were warmup, pass a lower --warmup-frames to summarize them",
+                self.warmed_up
+            );
+            return;
+        }
+        print_latency_summary(&self.samples);
+        if !self.samples.is_empty() {
+            info!("  over timeout: {}", self.over_timeout);
+        }
+    }
+}
+
+/// The most recent durations of a timed phase, for a live view of the trend.
+pub struct RollingStats {
+    capacity: usize,
+    samples: VecDeque<Duration>,
+}
+
+impl RollingStats {
+    pub fn new(capacity: usize) -> RollingStats {
+        Self {
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(duration);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Mean and standard deviation of the samples, or `None` before the first one.
+    pub fn mean_and_std_dev(&self) -> Option<(Duration, Duration)> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let count = self.samples.len() as f64;
+        let mean = self.samples.iter().map(Duration::as_secs_f64).sum::<f64>() / count;
+        let variance = self
+            .samples
+            .iter()
+            .map(|sample| (sample.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / count;
+        Some((
+            Duration::from_secs_f64(mean),
+            Duration::from_secs_f64(variance.sqrt()),
+        ))
+    }
+}
+
+/// Counts frames and reports the frame rate once per second.
+pub struct FpsCounter {
+    frames: u32,
+    since: Instant,
+}
+
+impl FpsCounter {
+    pub fn new() -> FpsCounter {
+        Self {
+            frames: 0,
+            since: Instant::now(),
+        }
+    }
+
+    /// Counts a frame, returning the frame rate if a second has passed since the last report.
+    pub fn tick(&mut self) -> Option<f64> {
+        self.frames += 1;
+        let elapsed = self.since.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return None;
+        }
+
+        let fps = self.frames as f64 / elapsed.as_secs_f64();
+        self.frames = 0;
+        self.since = Instant::now();
+        Some(fps)
+    }
+}
+
+/// Logs min, max, mean and tail percentiles of `samples`.
+pub fn print_latency_summary(samples: &[Duration]) {
+    if samples.is_empty() {
+        info!("  no frames were rendered");
+        return;
+    }
+
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable();
+    let total: Duration = sorted.iter().sum();
+    let mean = total / sorted.len() as u32;
+
+    info!("  frames: {}", sorted.len());
+    info!("  min:   {:?}", sorted[0]);
+    info!("  max:   {:?}", sorted[sorted.len() - 1]);
+    info!("  mean:  {:?}", mean);
+    info!("  p50:   {:?}", percentile(&sorted, 50.0));
+    info!("  p95:   {:?}", percentile(&sorted, 95.0));
+    info!("  p99:   {:?}", percentile(&sorted, 99.0));
+    info!("  p99.9: {:?}", percentile(&sorted, 99.9));
+}
+
+/// Logs a table with a row of timings for each named set of `samples`. Rows
+/// that couldn't be measured give the reason instead.
+pub fn print_comparison(title: &str, key: &str, rows: &[(String, Result<&[Duration], &str>)]) {
+    info!("{}", title);
+    info!(
+        "  {:<12} {:>7} {:>12} {:>12} {:>12} {:>12}",
+        key, "frames", "mean", "p50", "p99", "max"
+    );
+    for (name, samples) in rows {
+        let samples = match samples {
+            Ok(samples) => samples,
+            Err(reason) => {
+                info!("  {:<12} failed: {}", name, reason);
+                continue;
+            }
+        };
+        if samples.is_empty() {
+            info!("  {:<12} {:>7}", name, 0);
+            continue;
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+        let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+        info!(
+            "  {:<12} {:>7} {:>12} {:>12} {:>12} {:>12}",
+            name,
+            sorted.len(),
+            format!("{:.2?}", mean),
+            format!("{:.2?}", percentile(&sorted, 50.0)),
+            format!("{:.2?}", percentile(&sorted, 99.0)),
+            format!("{:.2?}", sorted[sorted.len() - 1]),
+        );
+    }
+}
+
+/// Nearest-rank percentile of an already sorted, non-empty slice.
+pub fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: impl IntoIterator<Item = u64>) -> Vec<Duration> {
+        values.into_iter().map(Duration::from_millis).collect()
+    }
+
+    #[test]
+    fn percentile_of_a_single_sample_is_that_sample() {
+        let samples = millis([7]);
+        for p in [0.0, 50.0, 99.9, 100.0] {
+            assert_eq!(percentile(&samples, p), Duration::from_millis(7));
+        }
+    }
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let samples = millis(1..=10);
+        assert_eq!(percentile(&samples, 0.0), Duration::from_millis(1));
+        assert_eq!(percentile(&samples, 10.0), Duration::from_millis(1));
+        assert_eq!(percentile(&samples, 50.0), Duration::from_millis(5));
+        assert_eq!(percentile(&samples, 95.0), Duration::from_millis(10));
+        assert_eq!(percentile(&samples, 100.0), Duration::from_millis(10));
+    }
+
+    #[test]
+    fn p99_9_of_fewer_than_1000_samples_is_the_max() {
+        let samples = millis(1..=100);
+        assert_eq!(percentile(&samples, 99.9), Duration::from_millis(100));
+        assert_eq!(percentile(&samples, 99.0), Duration::from_millis(99));
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::stats;
+
+/// Steps through values of a setting, spending a fixed time on each and
+/// keeping every value's acquire times apart for an end-of-run comparison.
+pub struct Sweep<T> {
+    /// What's being swept, used as the table heading.
+    title: &'static str,
+    /// What to call each value in the table.
+    key: &'static str,
+    interval: Duration,
+    results: Vec<(T, Vec<Duration>)>,
+    current: usize,
+    /// When to move on to the next value, set by the first frame with each one.
+    switch_at: Option<Instant>,
+}
+
+impl<T: Copy + fmt::Debug> Sweep<T> {
+    /// Returns `None` if there are no values to sweep.
+    pub fn new(
+        title: &'static str,
+        key: &'static str,
+        values: &[T],
+        interval: Duration,
+    ) -> Option<Sweep<T>> {
+        if values.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            title,
+            key,
+            interval,
+            results: values.iter().map(|&value| (value, Vec::new())).collect(),
+            current: 0,
+            switch_at: None,
+        })
+    }
+
+    /// The value being measured, or `None` once every value has been.
+    pub fn current(&self) -> Option<T> {
+        self.results.get(self.current).map(|&(value, _)| value)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current >= self.results.len()
+    }
+
+    /// Records an acquire time for the current value. Returns the value to
+    /// switch to once the current one has had its time.
+    pub fn record(&mut self, now: Instant, duration: Duration) -> Option<T> {
+        let (_, samples) = self.results.get_mut(self.current)?;
+        samples.push(duration);
+
+        let switch_at = *self.switch_at.get_or_insert(now + self.interval);
+        if now < switch_at {
+            return None;
+        }
+        self.current += 1;
+        self.switch_at = None;
+        self.current()
+    }
+
+    /// Logs a table comparing acquire times across the swept values.
+    pub fn print_comparison(&self) {
+        let rows: Vec<_> = self
+            .results
+            .iter()
+            .map(|(value, samples)| (format!("{:?}", value), Ok(samples.as_slice())))
+            .collect();
+        stats::print_comparison(self.title, self.key, &rows);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn nothing_to_sweep() {
+        assert!(Sweep::<u32>::new("Sweep:", "value", &[], INTERVAL).is_none());
+    }
+
+    #[test]
+    fn moves_on_once_each_value_had_its_time() {
+        let mut sweep = Sweep::new("Sweep:", "value", &[1, 2], INTERVAL).unwrap();
+        let start = Instant::now();
+        let sample = Duration::from_millis(1);
+
+        assert_eq!(sweep.current(), Some(1));
+        assert_eq!(sweep.record(start, sample), None);
+        assert_eq!(sweep.record(start + INTERVAL / 2, sample), None);
+        assert_eq!(sweep.record(start + INTERVAL, sample), Some(2));
+        // The next value's time starts from its first frame.
+        assert_eq!(sweep.record(start + INTERVAL * 3, sample), None);
+        assert!(!sweep.is_finished());
+        assert_eq!(sweep.record(start + INTERVAL * 4, sample), None);
+        assert!(sweep.is_finished());
+        assert_eq!(sweep.current(), None);
+
+        // Frames after the sweep finished aren't recorded anywhere.
+        assert_eq!(sweep.record(start + INTERVAL * 5, sample), None);
+        let counts: Vec<_> = sweep
+            .results
+            .iter()
+            .map(|(_, samples)| samples.len())
+            .collect();
+        assert_eq!(counts, [3, 2]);
+    }
+}
@@ -0,0 +1,200 @@
+use log::warn;
+use wgpu::{
+    Adapter, CommandEncoder, Device, Extent3d, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+use crate::gpu::ErrorScope;
+
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// The attachments besides the final color target that a frame renders into.
+/// These have to be recreated whenever the color target changes size.
+pub struct RenderTargets {
+    format: TextureFormat,
+    sample_count: u32,
+    /// Width and height of every target.
+    size: (u32, u32),
+    depth: Option<TextureView>,
+    msaa: Option<TextureView>,
+    /// Rendered into instead of the real target, which it's then copied to.
+    offscreen: Option<(Texture, TextureView)>,
+}
+
+impl RenderTargets {
+    /// With `offscreen`, frames are rendered into a texture of their own that
+    /// has to be copied to the real target with `copy_offscreen`.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        depth: bool,
+        sample_count: u32,
+        offscreen: bool,
+    ) -> Result<RenderTargets, String> {
+        let mut targets = Self {
+            format,
+            sample_count,
+            size: (width, height),
+            depth: None,
+            msaa: None,
+            offscreen: None,
+        };
+        targets.create(device, width, height, depth, offscreen)?;
+        Ok(targets)
+    }
+
+    pub fn resize(&mut self, device: &Device, width: u32, height: u32) -> Result<(), String> {
+        let depth = self.depth.is_some();
+        let offscreen = self.offscreen.is_some();
+        self.create(device, width, height, depth, offscreen)
+    }
+
+    fn create(
+        &mut self,
+        device: &Device,
+        width: u32,
+        height: u32,
+        depth: bool,
+        offscreen: bool,
+    ) -> Result<(), String> {
+        let scope = ErrorScope::push(device, "the render targets");
+        self.size = (width, height);
+        self.offscreen = offscreen.then(|| {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some("Offscreen Texture"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: self.format,
+                usage: TextureUsages::RENDER_ATTACHMENT
+                    | TextureUsages::COPY_SRC
+                    | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            (texture, view)
+        });
+        self.depth = depth.then(|| {
+            create_view(
+                device,
+                "Depth Texture",
+                DEPTH_FORMAT,
+                width,
+                height,
+                self.sample_count,
+            )
+        });
+        self.msaa = (self.sample_count > 1).then(|| {
+            create_view(
+                device,
+                "Multisample Texture",
+                self.format,
+                width,
+                height,
+                self.sample_count,
+            )
+        });
+        scope.finish()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        self.format
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn depth_format(&self) -> Option<TextureFormat> {
+        self.depth.is_some().then_some(DEPTH_FORMAT)
+    }
+
+    pub fn depth(&self) -> Option<&TextureView> {
+        self.depth.as_ref()
+    }
+
+    /// The multisampled color target, resolved into the real target at the end of the pass.
+    pub fn msaa(&self) -> Option<&TextureView> {
+        self.msaa.as_ref()
+    }
+
+    pub fn offscreen(&self) -> Option<&TextureView> {
+        self.offscreen.as_ref().map(|(_, view)| view)
+    }
+
+    /// Records a copy of the offscreen texture into `target`, if rendering offscreen.
+    pub fn copy_offscreen(&self, encoder: &mut CommandEncoder, target: &Texture) {
+        if let Some((texture, _)) = &self.offscreen {
+            encoder.copy_texture_to_texture(
+                texture.as_image_copy(),
+                target.as_image_copy(),
+                texture.size(),
+            );
+        }
+    }
+}
+
+/// Returns `requested` if the adapter can multisample `format`, and the depth
+/// format when `depth` is set, at that count, otherwise falls back to no
+/// multisampling.
+pub fn supported_sample_count(
+    adapter: &Adapter,
+    format: TextureFormat,
+    depth: bool,
+    requested: u32,
+) -> u32 {
+    if requested <= 1 {
+        return requested;
+    }
+    let depth_format = depth.then_some(DEPTH_FORMAT);
+    let unsupported = std::iter::once(format).chain(depth_format).find(|&format| {
+        !adapter
+            .get_texture_format_features(format)
+            .flags
+            .sample_count_supported(requested)
+    });
+    if let Some(format) = unsupported {
+        warn!(
+            "{}x MSAA is not supported for {:?}, rendering without MSAA",
+            requested, format
+        );
+        return 1;
+    }
+    requested
+}
+
+fn create_view(
+    device: &Device,
+    label: &str,
+    format: TextureFormat,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+) -> TextureView {
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some(label),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+    texture.create_view(&TextureViewDescriptor::default())
+}
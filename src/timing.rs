@@ -0,0 +1,103 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, CommandEncoder, Device, Features, Maintain, MapMode,
+    QuerySet, QuerySetDescriptor, QueryType, Queue, RenderPassTimestampWrites, QUERY_SIZE,
+};
+
+use crate::gpu::ErrorScope;
+
+/// Timestamps written at the start of the first render pass and the end of the last.
+const QUERY_COUNT: u32 = 2;
+const BUFFER_SIZE: wgpu::BufferAddress = (QUERY_COUNT * QUERY_SIZE) as wgpu::BufferAddress;
+
+/// Measures how long the render passes take on the GPU with a pair of timestamp queries.
+pub struct GpuTimer {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+}
+
+impl GpuTimer {
+    /// Returns `None` if the device wasn't created with `TIMESTAMP_QUERY`.
+    pub fn new(device: &Device, queue: &Queue) -> Result<Option<GpuTimer>, String> {
+        if !device.features().contains(Features::TIMESTAMP_QUERY) {
+            return Ok(None);
+        }
+
+        let scope = ErrorScope::push(device, "the GPU timing queries");
+        let query_set = device.create_query_set(&QuerySetDescriptor {
+            label: Some("Render Pass Timestamps"),
+            ty: QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+        let resolve_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Timestamp Resolve Buffer"),
+            size: BUFFER_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Timestamp Readback Buffer"),
+            size: BUFFER_SIZE,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        scope.finish()?;
+
+        Ok(Some(Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+        }))
+    }
+
+    /// Timestamp writes to attach to one of the render passes being measured.
+    /// Only the `first` pass writes the start time and only the `last` the end.
+    pub fn timestamp_writes(&self, first: bool, last: bool) -> RenderPassTimestampWrites<'_> {
+        RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: first.then_some(0),
+            end_of_pass_write_index: last.then_some(1),
+        }
+    }
+
+    /// Copies the timestamps into the readback buffer. Must be recorded after the pass ends.
+    pub fn resolve(&self, encoder: &mut CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            BUFFER_SIZE,
+        );
+    }
+
+    /// Waits for the GPU and returns the duration of the measured pass. Must
+    /// be called after the encoder passed to `resolve` was submitted.
+    pub fn read(&self, device: &Device) -> Option<Duration> {
+        let slice = self.readback_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(Maintain::Wait);
+        receiver.recv().ok()?.ok()?;
+
+        let ticks = {
+            let data = slice.get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            timestamps[1].saturating_sub(timestamps[0])
+        };
+        self.readback_buffer.unmap();
+
+        Some(Duration::from_nanos(
+            (ticks as f64 * self.period as f64) as u64,
+        ))
+    }
+}
@@ -0,0 +1,104 @@
+use wgpu::{
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, BlendState,
+    Color, ColorTargetState, ColorWrites, CommandEncoder, Device, FilterMode, FragmentState,
+    LoadOp, MultisampleState, Operations, PrimitiveState, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, SamplerDescriptor,
+    ShaderModuleDescriptor, ShaderSource, StoreOp, TextureFormat, TextureView, VertexState,
+};
+
+use crate::gpu::ErrorScope;
+
+const SHADER: &str = include_str!("upscale.wgsl");
+
+/// Draws a texture stretched over the whole of another, for `--internal-res`.
+pub struct Upscaler {
+    pipeline: RenderPipeline,
+    bind_group: BindGroup,
+}
+
+impl Upscaler {
+    /// `source` has to have been created with `TEXTURE_BINDING`, and the targets
+    /// passed to `draw` have to be of `format`.
+    pub fn new(
+        device: &Device,
+        source: &TextureView,
+        format: TextureFormat,
+    ) -> Result<Upscaler, String> {
+        let scope = ErrorScope::push(device, "the upscale pipeline");
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Upscale Shader"),
+            source: ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("Upscale Pipeline"),
+            layout: None,
+            vertex: VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            multiview: None,
+        });
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("Upscale Sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Upscale Bind Group"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(source),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        scope.finish()?;
+
+        Ok(Self {
+            pipeline,
+            bind_group,
+        })
+    }
+
+    /// Records a pass that covers `target` with the source texture.
+    pub fn draw(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Upscale Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
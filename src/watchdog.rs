@@ -0,0 +1,99 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::error;
+
+/// Watches the render thread from a background thread and complains if a
+/// frame stays armed for longer than `limit`.
+pub struct Watchdog {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<Armed>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Armed {
+    /// The frame being watched and when it was armed.
+    frame: Option<(u64, Instant)>,
+    /// Set when the `Watchdog` is dropped, telling the thread to exit.
+    shutdown: bool,
+}
+
+impl Watchdog {
+    /// Spawns the watchdog thread. With `abort` set, the process aborts when a
+    /// frame blocks for too long so the OS can produce a core dump.
+    pub fn spawn(limit: Duration, abort: bool) -> Watchdog {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(Armed::default()),
+            changed: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        thread::Builder::new()
+            .name("watchdog".into())
+            .spawn(move || watch(&thread_shared, limit, abort))
+            .unwrap();
+
+        Self { shared }
+    }
+
+    pub fn arm(&self, frame: u64) {
+        self.shared.state.lock().unwrap().frame = Some((frame, Instant::now()));
+        self.shared.changed.notify_one();
+    }
+
+    pub fn disarm(&self) {
+        self.shared.state.lock().unwrap().frame = None;
+        self.shared.changed.notify_one();
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.changed.notify_one();
+    }
+}
+
+fn watch(shared: &Shared, limit: Duration, abort: bool) {
+    let mut state = shared.state.lock().unwrap();
+    // When the currently armed frame was armed and how long it may block
+    // before the next report, so a long stall is reported once per `limit`.
+    let mut watched: Option<(Instant, Duration)> = None;
+    while !state.shutdown {
+        let Some((frame, armed_at)) = state.frame else {
+            watched = None;
+            state = shared.changed.wait(state).unwrap();
+            continue;
+        };
+
+        let report_after = match watched {
+            Some((watched_at, after)) if watched_at == armed_at => after,
+            _ => limit,
+        };
+        let elapsed = armed_at.elapsed();
+        if elapsed < report_after {
+            state = shared
+                .changed
+                .wait_timeout(state, report_after - elapsed)
+                .unwrap()
+                .0;
+            continue;
+        }
+
+        error!(
+            "Watchdog: frame {} has been blocked for {:.1}s",
+            frame,
+            elapsed.as_secs_f64()
+        );
+        if abort {
+            error!("Watchdog: aborting");
+            std::process::abort();
+        }
+        watched = Some((armed_at, report_after + limit));
+    }
+}
@@ -0,0 +1,58 @@
+use std::process::Command;
+
+/// Renders a few frames off-screen on the software adapter, so it runs in CI
+/// without a real GPU or display.
+#[test]
+fn headless_renders_requested_frames() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wgpu_blocking"))
+        .args([
+            "--headless",
+            "10",
+            "--warmup-frames",
+            "0",
+            "--fallback-adapter",
+            "--backend",
+            "all",
+        ])
+        .env("RUST_LOG", "info")
+        .output()
+        .expect("failed to run wgpu_blocking");
+    let log = String::from_utf8_lossy(&output.stderr);
+
+    if log.contains("No fallback adapter is available") {
+        eprintln!("skipping, no fallback adapter is available: {}", log);
+        return;
+    }
+
+    assert!(output.status.success(), "headless run failed:\n{}", log);
+    assert!(
+        log.contains("frames: 10"),
+        "expected 10 rendered frames:\n{}",
+        log
+    );
+    assert!(!log.contains("Surface error"), "surface errors:\n{}", log);
+    assert!(!log.contains("Device lost"), "device was lost:\n{}", log);
+}
+
+/// Runs fewer frames than the default warmup, which still has to leave a summary.
+#[test]
+fn headless_run_within_warmup_is_summarized() {
+    let output = Command::new(env!("CARGO_BIN_EXE_wgpu_blocking"))
+        .args(["--headless", "5", "--fallback-adapter", "--backend", "all"])
+        .env("RUST_LOG", "info")
+        .output()
+        .expect("failed to run wgpu_blocking");
+    let log = String::from_utf8_lossy(&output.stderr);
+
+    if log.contains("No fallback adapter is available") {
+        eprintln!("skipping, no fallback adapter is available: {}", log);
+        return;
+    }
+
+    assert!(output.status.success(), "headless run failed:\n{}", log);
+    assert!(
+        log.contains("frames: 5"),
+        "expected 5 summarized frames:\n{}",
+        log
+    );
+}